@@ -0,0 +1,13 @@
+-- Add migration script here
+
+-- Files that failed to import
+CREATE TABLE IF NOT EXISTS import_failure (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    -- current path to file (in quarantine folder, if it was moved there)
+    path        TEXT NOT NULL UNIQUE,
+    -- path to file relative to input folder
+    orig_path   TEXT NOT NULL,
+    -- error chain
+    error       TEXT NOT NULL,
+    fail_time   INTEGER NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,10 @@
+-- Add migration script here
+
+-- Internal key-value settings
+CREATE TABLE IF NOT EXISTS settings (
+    key     TEXT PRIMARY KEY NOT NULL,
+    value   TEXT NOT NULL
+);
+
+-- Element pool was flat before layouts were introduced
+INSERT INTO settings (key, value) VALUES ('pool_layout', 'flat');
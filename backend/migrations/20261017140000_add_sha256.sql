@@ -0,0 +1,6 @@
+-- Add migration script here
+
+-- Add collision-resistant hash of element file
+ALTER TABLE element ADD COLUMN sha256 BLOB;
+CREATE INDEX IF NOT EXISTS element_sha256 ON element (sha256);
+-- RUN add_sha256
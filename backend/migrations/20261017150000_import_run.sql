@@ -0,0 +1,18 @@
+-- Add migration script here
+
+-- History of import runs
+CREATE TABLE IF NOT EXISTS import_run (
+    id                  INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    trigger             INTEGER NOT NULL,
+    start_time          INTEGER NOT NULL,
+    end_time            INTEGER NOT NULL,
+    files_seen          INTEGER NOT NULL,
+    imported            INTEGER NOT NULL,
+    duplicates          INTEGER NOT NULL,
+    failed              INTEGER NOT NULL,
+    metadata_fetched    INTEGER NOT NULL,
+    groups_formed       INTEGER NOT NULL,
+    thumbnails_made     INTEGER NOT NULL,
+    -- error that interrupted the run
+    error               TEXT
+);
@@ -0,0 +1,19 @@
+-- Add migration script here
+
+-- Path to file relative to input folder, elements imported before are left with NULL
+ALTER TABLE element ADD COLUMN orig_path TEXT;
+
+CREATE INDEX IF NOT EXISTS element_orig_path_idx ON element(orig_path);
+
+-- Directories of input folder, filled only if path collections are enabled
+CREATE TABLE IF NOT EXISTS collection (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    -- path relative to input folder
+    path        TEXT UNIQUE NOT NULL,
+    parent_id   INTEGER REFERENCES collection(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS collection_parent_idx ON collection(parent_id);
+
+-- Whether collections are in sync with element paths
+INSERT INTO settings (key, value) VALUES ('path_collections', '0');
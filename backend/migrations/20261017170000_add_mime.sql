@@ -0,0 +1,5 @@
+-- Add migration script here
+
+-- MIME type of element file, detected by content
+ALTER TABLE element ADD COLUMN mime TEXT;
+-- RUN add_mime
@@ -0,0 +1,11 @@
+-- Add migration script here
+
+-- Media properties of element file
+ALTER TABLE element ADD COLUMN size INTEGER;
+ALTER TABLE element ADD COLUMN width INTEGER;
+ALTER TABLE element ADD COLUMN height INTEGER;
+-- video duration in seconds
+ALTER TABLE element ADD COLUMN duration REAL;
+-- video codec
+ALTER TABLE element ADD COLUMN codec TEXT;
+-- RUN add_media_info
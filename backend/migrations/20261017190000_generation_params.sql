@@ -0,0 +1,21 @@
+-- Add migration script here
+
+-- Generation parameters, normalized across stable diffusion sources
+CREATE TABLE generation_params (
+    element_id  INTEGER NOT NULL PRIMARY KEY
+        REFERENCES element (id) ON UPDATE RESTRICT ON DELETE CASCADE,
+    steps       INTEGER,
+    sampler     TEXT,
+    cfg         REAL,
+    seed        INTEGER,
+    width       INTEGER,
+    height      INTEGER,
+    model       TEXT,
+    model_hash  TEXT,
+    denoise     REAL
+);
+
+CREATE INDEX generation_params_sampler_idx ON generation_params (sampler);
+CREATE INDEX generation_params_model_idx ON generation_params (model);
+CREATE INDEX generation_params_model_hash_idx ON generation_params (model_hash);
+-- RUN add_generation_params
@@ -0,0 +1,21 @@
+-- Add migration script here
+
+-- Known checkpoints, LoRAs and other models
+CREATE TABLE model (
+    id          INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    name        TEXT    NOT NULL COLLATE NOCASE,
+    model_type  INTEGER NOT NULL,
+    base_model  TEXT,
+    UNIQUE (name, model_type)
+);
+
+-- Hash variants of model files
+CREATE TABLE model_hash (
+    model_id    INTEGER NOT NULL
+        REFERENCES model (id) ON UPDATE RESTRICT ON DELETE CASCADE,
+    kind        INTEGER NOT NULL,
+    hash        TEXT    NOT NULL,
+    PRIMARY KEY (model_id, kind)
+);
+
+CREATE INDEX model_hash_hash_idx ON model_hash (hash);
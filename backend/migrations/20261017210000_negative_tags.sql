@@ -0,0 +1,13 @@
+-- Add migration script here
+
+-- Join table for element and tags from its negative prompt
+CREATE TABLE element_neg_tag (
+    element_id INTEGER NOT NULL
+        REFERENCES element (id) ON UPDATE RESTRICT ON DELETE CASCADE,
+    tag_id     INTEGER NOT NULL
+        REFERENCES tag (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    PRIMARY KEY (element_id, tag_id)
+);
+
+CREATE INDEX element_neg_tag_tag_id_idx ON element_neg_tag (tag_id);
+-- RUN add_negative_tags
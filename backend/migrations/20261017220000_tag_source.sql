@@ -0,0 +1,7 @@
+-- Add migration script here
+
+-- Importer id of the parser that added tag to element, 
+-- NULL for manually added tags and tags from path.
+-- Used to replace parser tags when element is re-parsed
+ALTER TABLE element_tag ADD COLUMN source INTEGER;
+-- RUN mark_parser_tags
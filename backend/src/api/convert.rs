@@ -1,4 +1,4 @@
-use crate::{model, CONFIG, config::StaticFolder};
+use crate::{model, CONFIG, config::StaticFolder, util};
 use nndb_common::model as api;
 
 impl StaticFolder {
@@ -16,18 +16,19 @@ impl From<model::read::Element> for api::Element {
     fn from(value: model::read::Element) -> Self {
         Self {
             id: value.id,
-            url: CONFIG.element_pool.url(&value.filename),
+            url: CONFIG.element_pool.url(&CONFIG.pool_layout.rel_path(&value.filename)),
             broken: value.broken,
             thumb_url: value.has_thumb.then(|| {
-                let name = value
-                    .filename
-                    .split('.')
-                    .next()
-                    .unwrap()
-                    .to_string() + ".jpeg";
-                CONFIG.thumbnails_folder.url(&name)
+                let name = util::thumbnail_name(&value.filename);
+                CONFIG.thumbnails_folder.url(&CONFIG.pool_layout.rel_path(&name))
             }),
             animated: value.animated,
+            mime: value.mime,
+            width: value.width,
+            height: value.height,
+            size: value.size.map(|s| s as u64),
+            duration: value.duration,
+            codec: value.codec,
         }
     }
 }
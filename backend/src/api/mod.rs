@@ -1,18 +1,27 @@
+use std::{time::{SystemTime, UNIX_EPOCH}, path::Path};
+
+use actix_multipart::{Multipart, MultipartError};
 use actix_web::{Responder, get, web::{self, Json}, post};
+use anyhow::{Context, anyhow};
+use chrono::Utc;
+use futures::TryStreamExt;
 use itertools::Itertools;
 use nndb_common::*;
+use tokio::io::AsyncWriteExt;
 use tracing::{info, error};
 
 use crate::{
     dao::STORAGE, 
-    model::{write, TagType}, 
+    model::{write::{self, AddStatus}, TagType, ImportTrigger}, 
     util, 
     service::{
         SCAN_FILES_LOCK, UPDATE_METADATA_LOCK, GROUP_ELEMENTS_LOCK, 
-        MAKE_THUMBNAILS_LOCK, self, FETCH_WIKI_LOCK
+        MAKE_THUMBNAILS_LOCK, self, FETCH_WIKI_LOCK, PREVIEW_IMPORT_LOCK,
+        SCAN_MODELS_LOCK, REPARSE_METADATA_LOCK
     }, 
     log_n_ok, 
     log_n_bail, 
+    CONFIG,
 };
 
 mod convert;
@@ -22,6 +31,13 @@ use convert::IntoVec;
 /// Tag autocompletion max tags
 const TAG_LIMIT: u32 = 15;
 
+/// Max import runs returned in history
+const IMPORT_HISTORY_LIMIT: u32 = 100;
+
+/// Folder inside temp folder where uploaded files are stored before import.
+/// Element pool may be served, so files are not staged there
+const UPLOAD_FOLDER: &str = "nndb-upload";
+
 /// Element search
 #[post("/v1/search")]
 pub async fn search_elements(Json(req): Json<SearchRequest>) -> impl Responder {
@@ -79,6 +95,36 @@ pub async fn element(id: web::Path<u32>) -> impl Responder {
     }
 }
 
+/// Find element id by md5 or sha256 hash (hex)
+#[get("/v1/element_by_hash/{hash}")]
+pub async fn element_by_hash(hash: web::Path<String>) -> impl Responder {
+    let res = if let Some(md5) = util::parse_hex(&hash) {
+        STORAGE.get_element_id_by_hash(&md5).await
+    } else if let Some(sha256) = util::parse_hex(&hash) {
+        STORAGE.get_element_id_by_sha256(&sha256).await
+    } else {
+        log_n_bail!("expected md5 or sha256 hex string")
+    };
+
+    match res {
+        Ok(id) => Ok(Json(id)),
+        Err(e) => log_n_bail!("failed to find element by hash", ?e)
+    }
+}
+
+/// Nested collections of input folder directory
+#[post("/v1/collections")]
+pub async fn collections(Json(req): Json<CollectionsRequest>) -> impl Responder {
+    if !CONFIG.path_collections {
+        log_n_bail!("path collections are disabled");
+    }
+
+    match STORAGE.get_collections(req.parent.as_deref()).await {
+        Ok(collections) => Ok(Json(CollectionsResponse { collections })),
+        Err(e) => log_n_bail!("failed to get collections", ?e)
+    }
+}
+
 /// Tag data and aliases
 #[get("/v1/tag/{id}")]
 pub async fn tag_data(id: web::Path<u32>) -> impl Responder {
@@ -151,6 +197,9 @@ pub async fn import_status() -> impl Responder {
         group_elements: GROUP_ELEMENTS_LOCK.state(),
         make_thumbnails: MAKE_THUMBNAILS_LOCK.state(),
         wiki_fetch: FETCH_WIKI_LOCK.state(),
+        import_preview: PREVIEW_IMPORT_LOCK.state(),
+        model_scan: SCAN_MODELS_LOCK.state(),
+        reparse_metadata: REPARSE_METADATA_LOCK.state(),
     };
 
     Json(status)
@@ -185,9 +234,9 @@ pub async fn tags_edit(Json(req): Json<EditTagsRequest>) -> impl Responder {
 pub async fn control(Json(req): Json<ControlRequest>) -> impl Responder {
     tokio::spawn(async move {
         info!("Processing control request {req:?}");
-        let res = match req {
+        let res = match &req {
             ControlRequest::StartImport =>
-                service::manual_import().await,
+                service::import_pass(ImportTrigger::Manual).await.map(|_| ()),
             ControlRequest::UpdateTagCount => 
                 STORAGE.update_tag_count().await,
             ControlRequest::ClearGroupData => 
@@ -202,10 +251,27 @@ pub async fn control(Json(req): Json<ControlRequest>) -> impl Responder {
             ControlRequest::RetryImports => 
                 match STORAGE.unmark_failed_imports().await {
                     e @ Err(_) => e,
-                    Ok(_) => service::update_metadata().await
+                    Ok(_) => service::update_metadata().await.map(|_| ())
                 },
             ControlRequest::FetchWikis => 
                 service::update_danbooru_wikis().await,
+            ControlRequest::ScanModels =>
+                match tokio::task::spawn_blocking(service::scan_models)
+                    .await {
+                Ok(res) => res,
+                // Convert join error
+                Err(e) => Err(e.into())
+            },
+            ControlRequest::ReparseMetadata { query, source } => {
+                let (query, source) = (query.clone(), *source);
+                match tokio::task::spawn_blocking(move || 
+                    service::reparse_metadata(query.as_deref(), source).map(|_| ())
+                ).await {
+                    Ok(res) => res,
+                    // Convert join error
+                    Err(e) => Err(e.into())
+                }
+            },
         };
 
         match res {
@@ -217,6 +283,60 @@ pub async fn control(Json(req): Json<ControlRequest>) -> impl Responder {
     "null"
 }
 
+/// Show what will happen to files in input folder on import
+#[get("/v1/import_preview")]
+pub async fn import_preview() -> impl Responder {
+    match service::preview_import().await {
+        Ok(Some(files)) => Ok(Json(ImportPreviewResponse { files })),
+        Ok(None) => log_n_bail!("import preview is already running"),
+        Err(e) => log_n_bail!("failed to preview import", ?e)
+    }
+}
+
+/// Files that failed to import
+#[get("/v1/import_failures")]
+pub async fn import_failures() -> impl Responder {
+    match STORAGE.get_import_failures().await {
+        Ok(failures) => Ok(Json(ImportFailuresResponse { failures })),
+        Err(e) => log_n_bail!("failed to get import failures", ?e)
+    }
+}
+
+/// Latest import runs
+#[get("/v1/import_history")]
+pub async fn import_history() -> impl Responder {
+    match STORAGE.get_import_history(IMPORT_HISTORY_LIMIT).await {
+        Ok(runs) => Ok(Json(ImportHistoryResponse { runs })),
+        Err(e) => log_n_bail!("failed to get import history", ?e)
+    }
+}
+
+/// Retry or discard file that failed to import
+#[post("/v1/import_failure")]
+pub async fn import_failure(Json(req): Json<ImportFailureRequest>) -> impl Responder {
+    let res = match req.action {
+        ImportFailureAction::Retry => service::retry_import_failure(req.id).await,
+        ImportFailureAction::Discard => service::discard_import_failure(req.id).await,
+    };
+
+    match res {
+        Ok(_) => log_n_ok!("handled import failure", id=req.id, action=?req.action),
+        Err(e) => log_n_bail!("failed to handle import failure", ?e)
+    }
+}
+
+/// Add models to model registry.
+///
+/// Body is either JSON array or CSV list of models, 
+/// see [crate::model::registry::parse_list]
+#[post("/v1/models")]
+pub async fn import_models(body: String) -> impl Responder {
+    match service::import_model_list(&body).await {
+        Ok(count) => log_n_ok!("imported model list", count),
+        Err(e) => log_n_bail!("failed to import model list", ?e)
+    }
+}
+
 #[get("/v1/summary")]
 pub async fn summary() -> impl Responder {
     match STORAGE.get_summary().await {
@@ -226,3 +346,125 @@ pub async fn summary() -> impl Responder {
         Err(e) => log_n_bail!("failed to get DB summary", ?e)
     }
 }
+
+/// Upload files and import them.
+///
+/// Multipart fields:
+/// - `file` - element file, can be repeated
+/// - `tags` - whitespace-separated tags that will be added to each file
+/// - `source` - link to source of the files
+#[post("/v1/upload")]
+pub async fn upload(mut payload: Multipart) -> impl Responder {
+    let start = Utc::now();
+    // Each upload gets its own folder to keep original filenames
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let dir = std::env::temp_dir()
+        .join(UPLOAD_FOLDER)
+        .join(nanos.to_string());
+    
+    if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+        log_n_bail!("failed to create upload folder", ?e);
+    }
+
+    // Multipart errors are not thread safe
+    let multipart_err = |e: MultipartError| anyhow!("{e}");
+
+    // Save files and collect other fields
+    let res = async {
+        let mut files = vec![];
+        let mut tags = vec![];
+        let mut source = None;
+
+        while let Some(mut field) = payload.try_next().await.map_err(multipart_err)? {
+            match field.name() {
+                Some("file") => {
+                    // Strip any directories client could send
+                    let filename = field
+                        .content_disposition()
+                        .and_then(|cd| cd.get_filename())
+                        .and_then(|f| Path::new(f).file_name())
+                        .map(|f| f.to_owned())
+                        .context("expected filename")?;
+
+                    // Files with the same name go to different subfolders
+                    let path = dir.join(files.len().to_string()).join(filename);
+                    tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+                    let mut file = tokio::fs::File::create(&path).await?;
+                    while let Some(chunk) = field.try_next().await.map_err(multipart_err)? {
+                        file.write_all(&chunk).await?;
+                    }
+                    files.push(path);
+                },
+                Some(name @ ("tags" | "source")) => {
+                    let name = name.to_owned();
+                    let mut text = vec![];
+                    while let Some(chunk) = field.try_next().await.map_err(multipart_err)? {
+                        text.extend_from_slice(&chunk);
+                    }
+                    let text = String::from_utf8_lossy(&text).into_owned();
+                    
+                    if name == "tags" {
+                        tags.extend(text.split_whitespace().map(str::to_owned));
+                    } else if !text.is_empty() {
+                        source = Some(text);
+                    }
+                },
+                _ => ()
+            }
+        }
+
+        anyhow::Ok((files, tags, source))
+    }.await;
+
+    let (files, tags, source) = match res {
+        Ok(v) => v,
+        Err(e) => {
+            tokio::fs::remove_dir_all(&dir).await.ok();
+            log_n_bail!("failed to receive uploaded files", ?e);
+        }
+    };
+
+    let filenames = files.iter()
+        .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+        .collect_vec();
+
+    let res = service::import_uploaded(files, tags, source).await;
+
+    // Imported files are moved or copied to pool already
+    if let Err(e) = tokio::fs::remove_dir_all(&dir).await {
+        error!(?e, "failed to remove upload folder");
+    }
+
+    let statuses = match res {
+        Ok(s) => s,
+        Err(e) => log_n_bail!("failed to import uploaded files", ?e)
+    };
+
+    let stats = service::count_statuses(&statuses);
+    if !statuses.is_empty() {
+        // Make thumbnails, group, etc. and record upload in import history
+        tokio::spawn(async move {
+            if let Err(e) = service::finish_import(ImportTrigger::Upload, start, stats).await {
+                error!(?e, "failed to process uploaded elements");
+            }
+        });
+    }
+    
+    let files = filenames.into_iter()
+        .zip(statuses)
+        .map(|(filename, status)| UploadResult {
+            filename,
+            status: match status {
+                AddStatus::Added(id) => UploadStatus::Added { id },
+                AddStatus::Duplicate(id) => UploadStatus::Duplicate { id },
+                AddStatus::Failed(e) => UploadStatus::Failed { error: format!("{e:#}") },
+            }
+        })
+        .collect();
+    
+    info!("Uploaded files");
+    Ok(Json(UploadResponse { files }))
+}
@@ -0,0 +1,93 @@
+//! Extraction of archives dropped into `CONFIG.input_folder`
+//!
+//! Archive contents are extracted to staging folder inside element pool,
+//! mirroring input folder structure, with archive replaced by folder named after its stem.
+//! E.g. `input/TAG.artist.foo.zip` containing `bar/00001.png` will be extracted
+//! to `<staging>/TAG.artist.foo/bar/00001.png`, so path tags still work
+
+use std::{path::{Path, PathBuf}, fs::File, io::BufReader, time::{SystemTime, UNIX_EPOCH}};
+
+use anyhow::Context;
+use flate2::read::GzDecoder;
+
+use crate::{service, CONFIG};
+
+/// Folder inside element pool where archives are extracted before import
+const EXTRACT_FOLDER: &str = ".extract";
+
+/// Supported archive suffixes, longest first
+const ARCHIVE_SUFFIXES: &[&str] = &[".tar.gz", ".tgz", ".tar", ".zip"];
+
+/// Get supported archive suffix of `path`
+fn archive_suffix(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    ARCHIVE_SUFFIXES
+        .iter()
+        .find(|s| name.ends_with(*s))
+        .copied()
+}
+
+/// Check if file at `path` is a supported archive
+pub fn is_archive(path: &Path) -> bool {
+    archive_suffix(path).is_some()
+}
+
+/// Create new unique staging folder
+pub fn new_staging() -> anyhow::Result<PathBuf> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .as_nanos();
+
+    let dir = CONFIG.element_pool.path
+        .join(EXTRACT_FOLDER)
+        .join(nanos.to_string());
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(dir)
+}
+
+/// Get path of extracted file as if archive was unpacked in place,
+/// relative to input folder.
+/// Returns `None` if file wasn't extracted from archive
+pub fn staged_rel_path(path: &Path) -> Option<&Path> {
+    let rel = path.strip_prefix(CONFIG.element_pool.path.join(EXTRACT_FOLDER)).ok()?;
+    // Skip staging folder
+    let staging = rel.iter().next()?;
+    rel.strip_prefix(staging).ok()
+}
+
+/// Extract `archive` to `staging` folder.
+/// Returns importable files found in archive
+pub fn extract(archive: &Path, staging: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let suffix = archive_suffix(archive).context("not an archive")?;
+    let name = archive
+        .file_name()
+        .and_then(|n| n.to_str())
+        .context("failed to convert archive name")?;
+    let stem = &name[..name.len() - suffix.len()];
+
+    let rel_dir = archive
+        .parent()
+        .and_then(|p| p.strip_prefix(&CONFIG.input_folder).ok())
+        .unwrap_or(Path::new(""));
+
+    let dst = staging.join(rel_dir).join(stem);
+    std::fs::create_dir_all(&dst)?;
+
+    let file = BufReader::new(File::open(archive)?);
+
+    // Both extractors skip entries that point outside of `dst`
+    match suffix {
+        ".zip" => zip::ZipArchive::new(file)?.extract(&dst)?,
+        ".tar" => tar::Archive::new(file).unpack(&dst)?,
+        _ => tar::Archive::new(GzDecoder::new(file)).unpack(&dst)?,
+    }
+
+    // Nested archives are not extracted
+    let files = service::find_files(&dst)
+        .into_iter()
+        .filter(|p| !is_archive(p))
+        .collect();
+
+    Ok(files)
+}
@@ -29,6 +29,47 @@ pub enum ReadFiles {
     Sequential,
 }
 
+/// How files are placed in element pool and thumbnails folder
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")] 
+pub enum PoolLayout {
+    /// All files in one folder: `abcdef….png`
+    #[default]
+    Flat,
+    /// Files in nested folders named after first hash bytes: `ab/cd/abcdef….png`
+    Sharded,
+}
+
+impl PoolLayout {
+    /// Get layout name
+    pub fn name(&self) -> &'static str {
+        match self {
+            PoolLayout::Flat => "flat",
+            PoolLayout::Sharded => "sharded",
+        }
+    }
+
+    /// Parse layout name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "flat" => Some(Self::Flat),
+            "sharded" => Some(Self::Sharded),
+            _ => None
+        }
+    }
+
+    /// Path to file relative to the pool folder, with `/` as separator
+    pub fn rel_path(&self, filename: &str) -> String {
+        match self {
+            PoolLayout::Flat => filename.to_owned(),
+            PoolLayout::Sharded => match (filename.get(0..2), filename.get(2..4)) {
+                (Some(a), Some(b)) => format!("{a}/{b}/{filename}"),
+                _ => filename.to_owned(),
+            },
+        }
+    }
+}
+
 #[derive(Deserialize, Clone, Copy)]
 #[serde(rename_all = "lowercase")] 
 pub enum LogLevel {
@@ -62,12 +103,34 @@ pub struct Config {
     pub testing_mode: bool,
     /// If true, files in input_folder will be scanned periodically
     pub auto_scan_files: bool,
+    /// If true, input_folder will be watched for new files,
+    /// which will be imported as soon as they are written
+    #[serde(default)]
+    pub watch_input_folder: bool,
     /// Set max log level
     pub log_level: LogLevel,
     /// Directory where renamed element files will be placed.
     pub element_pool: StaticFolder,
+    /// Layout of element pool and thumbnails folder.
+    /// Existing files are moved on startup if layout was changed
+    #[serde(default)]
+    pub pool_layout: PoolLayout,
+    /// Make collection of each directory level of input folder
+    #[serde(default)]
+    pub path_collections: bool,
+    /// Add tags from NovelAI character captions with `Character` type
+    #[serde(default)]
+    pub novelai_character_tags: bool,
     /// Directory that will be scanned to find new element files
     pub input_folder: String,
+    /// Files that failed to import will be moved to this directory.
+    /// If not set, they will be left in input folder
+    #[serde(default)]
+    pub quarantine_folder: Option<PathBuf>,
+    /// Folder with checkpoints, LoRAs and other models,
+    /// that will be scanned to fill model registry
+    #[serde(default)]
+    pub models_folder: Option<PathBuf>,
     /// Serve thumbnails from this folder
     pub thumbnails_folder: StaticFolder,
     /// IP address to bind server to
@@ -81,14 +144,16 @@ pub struct Config {
     /// Pixiv fetcher credentials
     pub pixiv_credentials: Option<PixivCreds>,
     /// Path to ffmpeg.
-    /// Required to generate thumbnails for animation
+    /// Required to generate thumbnails for animation.
+    /// ffprobe is expected to be in the same folder
     pub ffmpeg_path: Option<String>,
     /// How to read files:
     /// - sequential: use one thread,
     /// - parallel: use multiple threads.
     pub read_files: ReadFiles,
     /// Max number of files stored in memory at the same time.
-    /// Files data are read first to memory, then hashed and freed.
+    /// Images are read whole to memory, then hashed, decoded and freed.
+    /// Animations (videos) are streamed from disk, but still count towards this limit.
     /// Bigger values can speed up file scanning, but may use more memory.
     pub max_files_in_memory: u32,
 }
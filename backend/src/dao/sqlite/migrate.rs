@@ -1,9 +1,19 @@
-use std::{collections::HashMap, ops::ControlFlow};
+use std::{collections::{HashMap, HashSet}, ops::ControlFlow, path::Path};
 
 use anyhow::bail;
+use itertools::Itertools;
 use sqlx::{SqlitePool, SqliteConnection, migrate::{Migrate, MigrationType}};
+use nndb_common::MetadataSource;
 use tracing::{info, warn};
-use crate::{CONFIG, import::{ElementPrefab, Parser, Fetcher}, model::read::PendingImport};
+use crate::{
+    CONFIG, 
+    import::{self, ElementPrefab, Parser, Fetcher, FileType}, 
+    model::{read::PendingImport, write::{MediaInfo, Tag}, TagType}, 
+    config::PoolLayout, 
+    util
+};
+
+use super::{Sqlite, TAG_CHUNK_SIZE};
 
 /// Run migrations with ability to call rust procedures.
 ///
@@ -50,6 +60,135 @@ pub async fn run_migrations(pool: &SqlitePool) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Move element files and thumbnails to match `CONFIG.pool_layout`.
+/// Does nothing if files are already placed according to it.
+///
+/// Interrupted move can be safely resumed on next run
+pub async fn relayout_pool(pool: &SqlitePool) -> anyhow::Result<()> {
+    let current = stored_layout(&mut *pool.acquire().await?).await?;
+    let target = CONFIG.pool_layout;
+    
+    if current == target {
+        return Ok(())
+    }
+
+    let files: Vec<String> = sqlx::query_scalar!(
+        "SELECT filename FROM element"  
+    )
+    .fetch_all(pool)
+    .await?;
+
+    info!(from=current.name(), to=target.name(), "changing element pool layout");
+
+    for (idx, file) in files.iter().enumerate() {
+        move_file(&CONFIG.element_pool.path, file, current, target)?;
+        move_file(&CONFIG.thumbnails_folder.path, &util::thumbnail_name(file), current, target)?;
+        
+        if (idx + 1) % 1000 == 0 {
+            info!("moved {}/{}", idx + 1, files.len());
+        }
+    }
+    
+    let name = target.name();
+    sqlx::query!(
+        "UPDATE settings SET value = ? WHERE key = 'pool_layout'",
+        name
+    )
+    .execute(pool)
+    .await?;
+
+    info!("element pool layout changed");
+
+    Ok(())
+}
+
+/// Fill collections from paths of existing elements, if `CONFIG.path_collections` 
+/// was enabled since last run. 
+/// If it is disabled, existing collections are kept, but not updated anymore
+pub async fn sync_collections(pool: &SqlitePool) -> anyhow::Result<()> {
+    let synced = sqlx::query_scalar!(
+        "SELECT value FROM settings WHERE key = 'path_collections'"
+    )
+    .fetch_optional(pool)
+    .await?
+    .is_some_and(|v| v == "1");
+
+    if synced == CONFIG.path_collections {
+        return Ok(())
+    }
+
+    let mut tx = pool.begin().await?;
+    
+    if CONFIG.path_collections {
+        info!("building path collections");
+
+        let paths: Vec<String> = sqlx::query_scalar!(
+            r#"SELECT DISTINCT orig_path as "orig_path!" 
+            FROM element 
+            WHERE orig_path IS NOT NULL"#
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for path in &paths {
+            Sqlite::add_collections_tx(&mut tx, path).await?;
+        }
+    }
+
+    let value = if CONFIG.path_collections { "1" } else { "0" };
+    sqlx::query!(
+        "INSERT INTO settings (key, value) VALUES ('path_collections', ?)
+        ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        value
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Get layout that element pool currently has
+async fn stored_layout(conn: &mut SqliteConnection) -> anyhow::Result<PoolLayout> {
+    let name = sqlx::query_scalar!(
+        "SELECT value FROM settings WHERE key = 'pool_layout'"
+    )
+    .fetch_optional(conn)
+    .await?;
+
+    match name {
+        Some(name) => PoolLayout::from_name(&name)
+            .ok_or_else(|| anyhow::anyhow!("unknown pool layout `{name}`")),
+        None => Ok(PoolLayout::Flat)
+    }
+}
+
+/// Move file inside `root` from one layout to another, removing empty shard folders.
+/// Missing files are skipped
+fn move_file(root: &Path, filename: &str, from: PoolLayout, to: PoolLayout) -> std::io::Result<()> {
+    let src = root.join(from.rel_path(filename));
+    let dst = root.join(to.rel_path(filename));
+
+    if !src.is_file() {
+        return Ok(())
+    }
+
+    if let Some(parent) = dst.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::rename(&src, &dst)?;
+
+    // Only succeeds if folder is empty
+    for dir in src.ancestors().skip(1).take_while(|d| *d != root) {
+        if std::fs::remove_dir(dir).is_err() {
+            break
+        }
+    }
+
+    Ok(())
+}
+
 pub fn get_procs(sql: &str) -> Vec<&str> {
     sql.lines()
         .filter(|l| l.starts_with("-- RUN"))
@@ -85,10 +224,7 @@ async fn run_proc(name: &str, tx: &mut SqliteConnection) -> anyhow::Result<Contr
         "add_raw_sd_meta" => {
             for file in files {
                 let path = CONFIG.element_pool.path.join(&file);
-                let prefab = ElementPrefab {
-                    data: std::fs::read(&path)?,
-                    path,
-                };
+                let prefab = ElementPrefab::load(path)?;
 
                 let parser = Parser::scan(&prefab);
                 if parser != Parser::Passthrough {
@@ -111,6 +247,290 @@ async fn run_proc(name: &str, tx: &mut SqliteConnection) -> anyhow::Result<Contr
             Ok(ControlFlow::Continue(()))
         }
 
+        "add_sha256" => {
+            // Files are moved to new layout only after migrations
+            let layout = stored_layout(&mut *tx).await?;
+            
+            for (idx, file) in files.iter().enumerate() {
+                let path = CONFIG.element_pool.path.join(layout.rel_path(file));
+                let res = std::fs::File::open(&path)
+                    .and_then(|f| util::hash_reader(std::io::BufReader::new(f)));
+                
+                match res {
+                    Ok((_, sha256)) => {
+                        let sha256 = sha256.as_slice();
+                        sqlx::query!(
+                            "UPDATE element SET sha256 = ? WHERE filename = ?",
+                            sha256,
+                            file
+                        )
+                        .execute(&mut *tx)
+                        .await?;
+                    },
+                    Err(e) => warn!(?e, file, "failed to hash element file"),
+                }
+
+                if (idx + 1) % 1000 == 0 {
+                    info!("processed {}/{}", idx + 1, files.len());
+                }
+            }
+
+            Ok(ControlFlow::Continue(()))
+        }
+
+        "add_generation_params" => {
+            let rows = sqlx::query!(
+                r#"SELECT 
+                    element_id as "element_id!: u32", 
+                    importer_id as "source: MetadataSource", 
+                    raw_meta as "raw_meta!"
+                FROM metadata 
+                WHERE raw_meta IS NOT NULL"#
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+
+            for row in rows {
+                if let Some(params) = row.source.generation_params(&row.raw_meta) {
+                    Sqlite::add_generation_params_tx(tx, row.element_id, &params).await?;
+                }
+            }
+
+            Ok(ControlFlow::Continue(()))
+        }
+
+        "add_negative_tags" => {
+            let rows = sqlx::query!(
+                r#"SELECT 
+                    element_id as "element_id!: u32", 
+                    importer_id as "source: MetadataSource", 
+                    raw_meta as "raw_meta!"
+                FROM metadata 
+                WHERE raw_meta IS NOT NULL"#
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+
+            for row in rows {
+                let tags = import::negative_tags(row.source, &row.raw_meta);
+                if !tags.is_empty() {
+                    Sqlite::add_neg_tags_tx(tx, row.element_id, &tags).await?;
+                }
+            }
+
+            Ok(ControlFlow::Continue(()))
+        }
+
+        "mark_parser_tags" => {
+            // Tags of element are inserted together on import, so rows inserted after 
+            // first row of next imported element were added later (tags edit, fetchers)
+            let first_rows = sqlx::query!(
+                r#"SELECT 
+                    element_id as "element_id!: u32", 
+                    MIN(rowid) as "first_row!: i64"
+                FROM element_tag
+                GROUP BY element_id
+                ORDER BY element_id DESC"#
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+
+            let mut import_end = HashMap::new();
+            let mut next_first = i64::MAX;
+            for row in first_rows {
+                import_end.insert(row.element_id, next_first);
+                next_first = next_first.min(row.first_row);
+            }
+
+            let rows = sqlx::query!(
+                r#"SELECT 
+                    m.element_id as "element_id!: u32", 
+                    m.importer_id as "source: MetadataSource", 
+                    m.raw_meta,
+                    e.orig_path
+                FROM metadata m
+                INNER JOIN element e ON e.id = m.element_id"#
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+
+            let sidecars: HashMap<_, _> = rows
+                .iter()
+                .filter(|r| r.source == MetadataSource::Sidecar)
+                .filter_map(|r| Some((r.element_id, r.raw_meta.as_deref()?)))
+                .collect();
+
+            let (mut marked, mut kept) = (0, 0);
+            for row in rows.iter().filter(|r| Parser::from_source(r.source).is_some()) {
+                let end = import_end.get(&row.element_id).copied().unwrap_or(i64::MAX);
+                let window = sqlx::query!(
+                    r#"SELECT et.rowid as "row!: i64", t.tag_name
+                    FROM element_tag et
+                    INNER JOIN tag t ON t.id = et.tag_id
+                    WHERE et.element_id = ? AND et.rowid < ?
+                    ORDER BY et.rowid"#,
+                    row.element_id, end
+                )
+                .fetch_all(&mut *tx)
+                .await?;
+
+                let mut manual: HashSet<_> = sidecars
+                    .get(&row.element_id)
+                    .map(|text| import::sidecar_tags(text))
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|t| t.name().to_owned())
+                    .collect();
+
+                let parsed_len = match &row.orig_path {
+                    Some(orig_path) => {
+                        manual.extend(util::get_tags_from_path(Path::new(orig_path))
+                            .iter()
+                            .map(|t| t.name().to_owned())
+                        );
+                        window.len()
+                    },
+                    // Path is unknown for elements imported before it was stored.
+                    // Path tags were inserted after parser ones, so trailing tags 
+                    // that neither occur in raw metadata nor are produced by current parser
+                    // are considered path tags
+                    None => {
+                        let raw = row.raw_meta
+                            .as_deref()
+                            .and_then(|raw| Tag::new(raw, None, TagType::Tag))
+                            .map(|t| t.name().to_owned())
+                            .unwrap_or_default();
+                        let parsed: HashSet<_> = import::parser_tags(row.source, row.raw_meta.as_deref())
+                            .iter()
+                            .map(|t| t.name().to_owned())
+                            .collect();
+
+                        window
+                            .iter()
+                            .rposition(|r| raw.contains(&r.tag_name) || parsed.contains(&r.tag_name))
+                            .map_or(0, |idx| idx + 1)
+                    },
+                };
+
+                let rowids = window[..parsed_len]
+                    .iter()
+                    .filter(|r| !manual.contains(&r.tag_name))
+                    .map(|r| r.row)
+                    .collect_vec();
+                kept += (window.len() - rowids.len()) as u64;
+
+                for chunk in rowids.chunks(TAG_CHUNK_SIZE) {
+                    let rows = vec!["?"; chunk.len()].join(", ");
+                    let stmt = format!( // sql
+                        "UPDATE element_tag SET source = ? WHERE rowid IN ({rows})"
+                    );
+
+                    let mut query = sqlx::query(&stmt).bind(row.source);
+                    for rowid in chunk {
+                        query = query.bind(rowid);
+                    }
+                    marked += query.execute(&mut *tx).await?.rows_affected();
+                }
+            }
+
+            info!(marked, kept, "marked parser tags, path and sidecar tags are kept manual");
+
+            Ok(ControlFlow::Continue(()))
+        }
+
+        "add_mime" => {
+            // Files are moved to new layout only after migrations
+            let layout = stored_layout(&mut *tx).await?;
+
+            for (idx, file) in files.iter().enumerate() {
+                let path = CONFIG.element_pool.path.join(layout.rel_path(file));
+                match FileType::detect(&path) {
+                    Ok(Some(file_type)) => {
+                        sqlx::query!(
+                            "UPDATE element SET mime = ? WHERE filename = ?",
+                            file_type.mime,
+                            file
+                        )
+                        .execute(&mut *tx)
+                        .await?;
+                    },
+                    Ok(None) => warn!(file, "unknown element file type"),
+                    Err(e) => warn!(?e, file, "failed to read element file"),
+                }
+
+                if (idx + 1) % 1000 == 0 {
+                    info!("processed {}/{}", idx + 1, files.len());
+                }
+            }
+
+            Ok(ControlFlow::Continue(()))
+        }
+
+        "add_media_info" => {
+            // Files are moved to new layout only after migrations
+            let layout = stored_layout(&mut *tx).await?;
+
+            let animated: HashSet<String> = sqlx::query_scalar!(
+                "SELECT filename FROM element WHERE animated = 1"
+            )
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .collect();
+            
+            if !animated.is_empty() && CONFIG.ffmpeg_path.is_none() {
+                warn!("ffmpeg path is not configured, media info of videos won't be filled");
+            }
+
+            for (idx, file) in files.iter().enumerate() {
+                let path = CONFIG.element_pool.path.join(layout.rel_path(file));
+                
+                let size = match std::fs::metadata(&path) {
+                    Ok(meta) => meta.len() as i64,
+                    Err(e) => {
+                        warn!(?e, file, "failed to read element file");
+                        continue
+                    }
+                };
+
+                let media = if animated.contains(file) {
+                    util::probe_media(&path).ok()
+                } else {
+                    // Only header is read
+                    image::io::Reader::open(&path)
+                        .and_then(|r| r.with_guessed_format())
+                        .ok()
+                        .and_then(|r| r.into_dimensions().ok())
+                        .map(|(width, height)| MediaInfo {
+                            width: Some(width),
+                            height: Some(height),
+                            ..Default::default()
+                        })
+                }
+                .unwrap_or_default();
+
+                sqlx::query!(
+                    "UPDATE element 
+                    SET size = ?, width = ?, height = ?, duration = ?, codec = ? 
+                    WHERE filename = ?",
+                    size,
+                    media.width,
+                    media.height,
+                    media.duration,
+                    media.codec,
+                    file
+                )
+                .execute(&mut *tx)
+                .await?;
+
+                if (idx + 1) % 1000 == 0 {
+                    info!("processed {}/{}", idx + 1, files.len());
+                }
+            }
+
+            Ok(ControlFlow::Continue(()))
+        }
+
         "add_raw_pixiv_meta" => {
             let imports: Vec<PendingImport> = sqlx::query_as(
                 "SELECT e.*, m.importer_id
@@ -1,23 +1,24 @@
-use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 
 use futures::FutureExt;
 use itertools::Itertools;
 use moka::future::Cache;
-use nndb_common::search::Term;
-use nndb_common::{MetadataSource, search};
-use sqlx::Executor;
+use nndb_common::search::{Term, Range};
+use nndb_common::{MetadataSource, GenerationParams, search};
+use sqlx::{Executor, Connection};
 use sqlx::{SqlitePool, migrate::MigrateDatabase, SqliteConnection};
 use tokio::sync::RwLock;
 
-use crate::import::{Fetcher, FetchStatus};
+use crate::import::{Fetcher, FetchStatus, Parser};
 use crate::util;
 use crate::{
     model::{
-        write::{self, ElementWithMetadata}, 
+        write::{self, ElementWithMetadata, AddStatus}, 
         read::{self, PendingImport}, 
-        Summary, Md5Hash, GroupMetadata, UtcDateTime
+        Summary, Md5Hash, Sha256Hash, GroupMetadata, UtcDateTime, ImportFailure,
+        ImportRun, ImportStats, ImportTrigger, Collection, TagType,
+        registry::{Model, ModelType, HashKind, model_stem}
     }, 
     CONFIG
 };
@@ -27,6 +28,9 @@ use tracing::{error, warn};
 
 mod migrate;
 
+/// Max count of tags inserted by one statement
+const TAG_CHUNK_SIZE: usize = 1000;
+
 pub struct Sqlite {
     pool: SqlitePool,
     id_cache: Cache<String, Arc<Vec<u32>>>,
@@ -35,6 +39,18 @@ pub struct Sqlite {
 
 pub type StorageError = anyhow::Error;
 
+/// Make SQL condition that `expr` is in `range`
+fn range_condition(expr: &str, range: Range) -> String {
+    match range {
+        Range::Eq(v) => format!("AND {expr} = {v}"),
+        Range::Lt(v) => format!("AND {expr} < {v}"),
+        Range::Le(v) => format!("AND {expr} <= {v}"),
+        Range::Gt(v) => format!("AND {expr} > {v}"),
+        Range::Ge(v) => format!("AND {expr} >= {v}"),
+        Range::Between(from, to) => format!("AND {expr} BETWEEN {from} AND {to}"),
+    }
+}
+
 /// Private methods and associated functions
 impl Sqlite {
     async fn add_element_tx(
@@ -47,14 +63,26 @@ impl Sqlite {
         let time = util::get_file_datetime(&e.path).ok();
 
         let hash = e.hash.as_slice();
+        let sha256 = e.sha256.as_slice();
+        let size = e.size as i64;
         let id = sqlx::query!(
             r#"INSERT INTO element (
-                filename, orig_filename, hash, broken, animated, file_time
+                filename, orig_filename, orig_path, mime, 
+                size, width, height, duration, codec,
+                hash, sha256, broken, animated, file_time
             )
-            VALUES (?, ?, ?, ?, ?, ?)"#,
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
             e.filename,
             e.orig_filename,
+            e.orig_path,
+            e.mime,
+            size,
+            e.media.width,
+            e.media.height,
+            e.media.duration,
+            e.media.codec,
             hash,
+            sha256,
             e.broken,
             e.animated,
             time
@@ -71,6 +99,24 @@ impl Sqlite {
             meta
         ).await?;
 
+        // Keep caption file contents as separate metadata
+        if let Some(sidecar) = &e.sidecar {
+            let sidecar_meta = write::ElementMetadata {
+                src_link: None,
+                src_time: None,
+                raw_meta: Some(sidecar.text.clone()),
+                group: None,
+                tags: vec![],
+                neg_tags: vec![],
+            };
+            Self::add_metadata_tx(
+                tx, 
+                id as u32, 
+                MetadataSource::Sidecar, 
+                &sidecar_meta
+            ).await?;
+        }
+
         if let Some(sig) = e.signature {
             let sig = bytemuck::cast_slice(&sig);
             sqlx::query!(
@@ -78,12 +124,44 @@ impl Sqlite {
                 id,
                 sig
             )
-            .execute(tx)
+            .execute(&mut *tx)
             .await?;
         }
+
+        if CONFIG.path_collections {
+            Self::add_collections_tx(tx, &e.orig_path).await?;
+        }
         
         Ok(id as u32)
     }
+
+    /// Add collection for each directory of `orig_path`, if not exists
+    async fn add_collections_tx(
+        tx: &mut SqliteConnection,
+        orig_path: &str
+    ) -> Result<(), StorageError> {
+        let mut parent_id = None;
+        for dir in util::path_dirs(orig_path) {
+            sqlx::query!(
+                "INSERT INTO collection (path, parent_id) 
+                VALUES (?, ?)
+                ON CONFLICT (path) DO NOTHING",
+                dir, parent_id
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            parent_id = sqlx::query_scalar!(
+                r#"SELECT id as "id!: u32" FROM collection WHERE path = ?"#,
+                dir
+            )
+            .fetch_one(&mut *tx)
+            .await
+            .map(Some)?;
+        }
+
+        Ok(())
+    }
     
     async fn add_metadata_tx(
         tx: &mut SqliteConnection,
@@ -93,7 +171,11 @@ impl Sqlite {
     ) -> Result<(), StorageError> {
 
         if !meta.tags.is_empty() {
-            Self::add_tags_tx(tx, Some(element_id), &meta.tags).await?;
+            Self::add_tags_tx(tx, Some(element_id), Some(source), &meta.tags).await?;
+        }
+
+        if !meta.neg_tags.is_empty() {
+            Self::add_neg_tags_tx(tx, element_id, &meta.neg_tags).await?;
         }
        
         sqlx::query!(
@@ -110,10 +192,98 @@ impl Sqlite {
         )
         .execute(&mut *tx)
         .await?;
+
+        let params = meta.raw_meta
+            .as_deref()
+            .and_then(|raw| source.generation_params(raw));
+        if let Some(params) = params {
+            Self::add_generation_params_tx(tx, element_id, &params).await?;
+
+            let models = Self::resolve_models_tx(tx, &params).await?;
+            if !models.is_empty() {
+                Self::add_tags_tx(tx, Some(element_id), Some(source), &models).await?;
+            }
+        }
         
         Ok(())
     }
 
+    /// Add or replace generation parameters of element
+    async fn add_generation_params_tx(
+        tx: &mut SqliteConnection,
+        element_id: u32,
+        params: &GenerationParams
+    ) -> Result<(), StorageError> {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO generation_params (
+                element_id, steps, sampler, cfg, seed, 
+                width, height, model, model_hash, denoise)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            element_id,
+            params.steps,
+            params.sampler,
+            params.cfg,
+            params.seed,
+            params.width,
+            params.height,
+            params.model,
+            params.model_hash,
+            params.denoise
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Find checkpoint and LoRAs of generation parameters in model registry,
+    /// by hash or by name if hash is unknown.
+    /// Returns tags named after found models
+    async fn resolve_models_tx(
+        tx: &mut SqliteConnection,
+        params: &GenerationParams
+    ) -> Result<Vec<write::Tag>, StorageError> {
+        let checkpoint = (params.model.is_some() || params.model_hash.is_some())
+            .then_some((ModelType::Checkpoint, params.model.as_deref(), params.model_hash.as_deref()));
+        let loras = params.loras
+            .iter()
+            .map(|(name, hash)| (ModelType::Lora, Some(name.as_str()), hash.as_deref()));
+
+        let mut tags = vec![];
+        for (model_type, name, hash) in checkpoint.into_iter().chain(loras) {
+            let hash = hash.map(str::to_lowercase);
+            let by_hash = sqlx::query_scalar!(
+                "SELECT m.name FROM model m
+                JOIN model_hash h ON h.model_id = m.id
+                WHERE h.hash = ? AND m.model_type = ?
+                LIMIT 1",
+                hash,
+                model_type
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            let found = match (by_hash, name) {
+                (Some(found), _) => Some(found),
+                (None, Some(name)) => {
+                    let name = model_stem(name);
+                    sqlx::query_scalar!(
+                        "SELECT name FROM model WHERE name = ? AND model_type = ?",
+                        name,
+                        model_type
+                    )
+                    .fetch_optional(&mut *tx)
+                    .await?
+                },
+                (None, None) => None,
+            };
+
+            tags.extend(found.and_then(|name| write::Tag::new(&name, None, TagType::Model)));
+        }
+
+        Ok(tags)
+    }
+
     async fn add_fetch_status_tx(
         tx: &mut SqliteConnection,
         element_id: u32, 
@@ -144,58 +314,116 @@ impl Sqlite {
     }
 
     /// Add all tags from slice, optionally add join rows for element.
-    /// Increment tag's `count` if trying to insert duplicate and `element_id` is `Some`
+    /// Increment tag's `count` if trying to insert duplicate and `element_id` is `Some`.
+    ///
+    /// `source` is the parser that produced the tags, `None` for manually added ones.
+    /// Adding existing parser tag without source makes it manual
     async fn add_tags_tx<T>(
         tx: &mut SqliteConnection,
         element_id: Option<u32>, 
+        source: Option<MetadataSource>,
         tags: &[T]
     ) -> Result<(), StorageError> 
-    where T: AsRef<write::Tag> {    
-        for t in tags {
-            let t = t.as_ref();
-            let name = t.name();
-            let alt_name = t.alt_name();
-            let typ = t.tag_type();
-            
-            if let Some(id) = element_id {
+    where T: AsRef<write::Tag> {
+        // Tag repeated in one statement would be counted twice
+        let tags = tags.iter()
+            .map(AsRef::as_ref)
+            .unique_by(|t| t.name())
+            .collect_vec();
+
+        // Keep number of bound parameters below SQLite limit
+        for chunk in tags.chunks(TAG_CHUNK_SIZE) {
+            let on_conflict = match element_id {
                 // Update count if tag already exists
-                sqlx::query!(
-                    "INSERT INTO tag (tag_name, alt_name, tag_type, count)
-                    VALUES (?, ?, ?, 1)
-                    ON CONFLICT (tag_name) DO UPDATE SET count = count + 1",
-                    name,
-                    alt_name,
-                    typ
-                )
-                .execute(&mut *tx)
-                .await?;
-                
-                sqlx::query!(
-                    "INSERT INTO element_tag (element_id, tag_id)                 
-                    VALUES (?, (SELECT id FROM tag WHERE tag_name = ?))
-                    ON CONFLICT (element_id, tag_id) DO NOTHING",
-                    id, name
-                )
-                .execute(&mut *tx)
-                .await?;
-            } else {
-                // Just insert tag
-                sqlx::query!(
-                    "INSERT INTO tag (tag_name, alt_name, tag_type)
-                    VALUES (?, ?, ?)
-                    ON CONFLICT (tag_name) DO NOTHING",
-                    name,
-                    alt_name,
-                    typ
-                )
-                .execute(&mut *tx)
-                .await?;
+                Some(_) => "DO UPDATE SET count = count + 1",
+                None => "DO NOTHING",
+            };
+            let stmt = format!( // sql
+                "INSERT INTO tag (tag_name, alt_name, tag_type, count)
+                VALUES {values}
+                ON CONFLICT (tag_name) {on_conflict}",
+                values = vec!["(?, ?, ?, ?)"; chunk.len()].join(", ")
+            );
+
+            let count = element_id.is_some() as i64;
+            let mut query = sqlx::query(&stmt);
+            for t in chunk {
+                query = query
+                    .bind(t.name())
+                    .bind(t.alt_name())
+                    .bind(t.tag_type())
+                    .bind(count);
             }
+            query.execute(&mut *tx).await?;
+
+            if let Some(id) = element_id {
+                let stmt = format!( // sql
+                    "INSERT INTO element_tag (element_id, tag_id, source)
+                    SELECT ?, id, ? FROM tag WHERE tag_name IN ({names})
+                    ON CONFLICT (element_id, tag_id) 
+                    DO UPDATE SET source = NULL WHERE excluded.source IS NULL",
+                    names = vec!["?"; chunk.len()].join(", ")
+                );
+
+                let mut query = sqlx::query(&stmt).bind(id).bind(source);
+                for t in chunk {
+                    query = query.bind(t.name());
+                }
+                query.execute(&mut *tx).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add tags from negative prompt of element.
+    /// Tags are created if missing, but their counts are not changed
+    async fn add_neg_tags_tx<T>(
+        tx: &mut SqliteConnection,
+        element_id: u32,
+        tags: &[T]
+    ) -> Result<(), StorageError>
+    where T: AsRef<write::Tag> {
+        Self::add_tags_tx(tx, None, None, tags).await?;
+
+        let names = tags.iter()
+            .map(|t| t.as_ref().name())
+            .unique()
+            .collect_vec();
+
+        for chunk in names.chunks(TAG_CHUNK_SIZE) {
+            let stmt = format!( // sql
+                "INSERT INTO element_neg_tag (element_id, tag_id)
+                SELECT ?, id FROM tag WHERE tag_name IN ({names})
+                ON CONFLICT (element_id, tag_id) DO NOTHING",
+                names = vec!["?"; chunk.len()].join(", ")
+            );
+
+            let mut query = sqlx::query(&stmt).bind(element_id);
+            for name in chunk {
+                query = query.bind(name);
+            }
+            query.execute(&mut *tx).await?;
         }
 
         Ok(())
     }
 
+    async fn get_element_id_by_hash_tx(
+        tx: &mut SqliteConnection,
+        hash: &Md5Hash
+    ) -> Result<Option<u32>, StorageError> {
+        let hash = hash.as_slice();
+        let id = sqlx::query_scalar!(
+            r#"SELECT id as "id!: u32" FROM element WHERE hash = ?"#,
+            hash
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        Ok(id)
+    }
+
     async fn get_tag_data_tx(
         tx: &mut SqliteConnection,
         name: &str
@@ -288,15 +516,30 @@ impl Sqlite {
         
         let mut pos_tag_set = vec![];
         let mut neg_tag_set = vec![];
+        // Conditions on tags of negative prompt
+        let mut neg_prompt = vec![];
         
         for tag in search::parse_query(query) {
-            let Term::Tag(pos, name) = tag else { continue };
-
-            match (pos, Self::get_tag_data_tx(tx, name).await?) {
-                // Return empty vec if one of positive tags not in db
-                (true, None) => return Ok(vec![]),
-                (true, Some(data)) => pos_tag_set.push(data.id),
-                (false, Some(data)) => neg_tag_set.push(data.id),
+            match tag {
+                Term::Tag(pos, name) => match (pos, Self::get_tag_data_tx(tx, name).await?) {
+                    // Return empty vec if one of positive tags not in db
+                    (true, None) => return Ok(vec![]),
+                    (true, Some(data)) => pos_tag_set.push(data.id),
+                    (false, Some(data)) => neg_tag_set.push(data.id),
+                    _ => ()
+                },
+                Term::NegTag(pos, name) => match (pos, Self::get_tag_data_tx(tx, name).await?) {
+                    (true, None) => return Ok(vec![]),
+                    // Tag ids are integers, so they're formatted directly
+                    (pos, Some(data)) => neg_prompt.push(format!(
+                        "AND e.id {not} IN (
+                            SELECT element_id FROM element_neg_tag WHERE tag_id = {id}
+                        )",
+                        not = if pos { "" } else { "NOT" },
+                        id = data.id
+                    )),
+                    (false, None) => (),
+                },
                 _ => ()
             }
         }
@@ -304,12 +547,40 @@ impl Sqlite {
         let mut group = None;
         let mut ext_group = None;
         let mut metadata = None;
+        let mut md5 = None;
+        let mut sha256 = None;
+        let mut path = None;
+        let mut model = None;
+        let mut sampler = None;
+        let mut ranges = vec![];
+        let mut gen_ranges = vec![];
         for meta in search::parse_query(query) {
             match meta {
-                Term::Tag(..) => continue,
+                Term::Tag(..) | Term::NegTag(..) => continue,
                 Term::Group(id) => group = Some(id),
                 Term::ExtGroup(id) => ext_group = Some(id),
                 Term::Meta(m) => metadata = Some(format!("%{m}%")),
+                Term::Md5(hash) => md5 = Some(hash.to_owned()),
+                Term::Sha256(hash) => sha256 = Some(hash.to_owned()),
+                // Quotes inside quoted glob are escaped
+                Term::Path(glob) => path = Some(glob.replace("\\\"", "\"")),
+                Term::Width(r) => ranges.push(range_condition("e.width", r)),
+                Term::Height(r) => ranges.push(range_condition("e.height", r)),
+                Term::Ratio(r) => ranges.push(range_condition(
+                    "(e.width * 1.0 / e.height)",
+                    // Exact ratio can't be compared due to rounding of dimensions
+                    match r {
+                        Range::Eq(v) => Range::Between(v * 0.99, v * 1.01),
+                        r => r,
+                    }
+                )),
+                Term::Size(r) => ranges.push(range_condition("e.size", r)),
+                Term::Duration(r) => ranges.push(range_condition("e.duration", r)),
+                Term::Steps(r) => gen_ranges.push(range_condition("gp.steps", r)),
+                Term::Cfg(r) => gen_ranges.push(range_condition("gp.cfg", r)),
+                Term::Denoise(r) => gen_ranges.push(range_condition("gp.denoise", r)),
+                Term::Model(m) => model = Some(m.to_owned()),
+                Term::Sampler(s) => sampler = Some(s.to_owned()),
                 // We cannot respond with anything meaningful on this
                 Term::Raw(_) => return Ok(vec![]),
             }
@@ -346,11 +617,19 @@ impl Sqlite {
                 JOIN tag t ON t.id = et.tag_id
                 {join_group_meta}
                 {join_metadata}
+                {join_generation_params}
                 WHERE 
                     1
                     {cond_group}
                     {cond_ext_group}
                     {cond_metadata}
+                    {cond_md5}
+                    {cond_sha256}
+                    {cond_path}
+                    {cond_ranges}
+                    {cond_model}
+                    {cond_sampler}
+                    {cond_neg_prompt}
                 GROUP BY e.id
                 HAVING 
                     CASE ?1
@@ -380,6 +659,12 @@ impl Sqlite {
                 join_group_meta = group.is_some()
                     .then_some("JOIN group_metadata g ON g.element_id = e.id")
                     .unwrap_or_default(),
+                join_generation_params = 
+                    if model.is_some() || sampler.is_some() || !gen_ranges.is_empty() {
+                        "JOIN generation_params gp ON gp.element_id = e.id"
+                    } else {
+                        ""
+                    },
                 // Bind conditionals directly, they're integers anyway
                 cond_group = group
                     .map(|id| format!("AND g.group_id = {id}"))
@@ -389,10 +674,28 @@ impl Sqlite {
                     .unwrap_or_default(),
                 cond_metadata = metadata.is_some()
                     .then_some("AND m.raw_meta LIKE ?2")
-                    .unwrap_or_default()
+                    .unwrap_or_default(),
+                // Hashes are validated to be hex by parser
+                cond_md5 = md5
+                    .map(|h| format!("AND e.hash = X'{h}'"))
+                    .unwrap_or_default(),
+                cond_sha256 = sha256
+                    .map(|h| format!("AND e.sha256 = X'{h}'"))
+                    .unwrap_or_default(),
+                cond_path = if path.is_some() { "AND e.orig_path GLOB ?3" } else { "" },
+                // Range values are numbers parsed by search parser
+                cond_ranges = ranges.iter().chain(&gen_ranges).join(" "),
+                // LIKE is case-insensitive, so names can be typed in any case
+                cond_model = 
+                    if model.is_some() { "AND (gp.model LIKE ?4 OR gp.model_hash LIKE ?4)" } else { "" },
+                cond_sampler = if sampler.is_some() { "AND gp.sampler LIKE ?5" } else { "" },
+                cond_neg_prompt = neg_prompt.join(" "),
             ))
             .bind(pos_tags)
             .bind(metadata)
+            .bind(path)
+            .bind(model)
+            .bind(sampler)
             .fetch_all(&mut *tx)
             .await?;
             
@@ -442,6 +745,10 @@ impl Sqlite {
         let pool = SqlitePool::connect(url).await?;
         // Apply migrations if needed
         migrate::run_migrations(&pool).await?;
+        // Move files if pool layout was changed
+        migrate::relayout_pool(&pool).await?;
+        // Build collections if they were enabled
+        migrate::sync_collections(&pool).await?;
         
         Ok(Self {
             pool,
@@ -451,81 +758,131 @@ impl Sqlite {
     }
 
     /// Add all elements from slice (optionally with metadata)
-    /// Returns count of new elements
-    pub async fn add_elements<E>(&self, elements: &[E]) -> Result<u32, StorageError>
+    /// Returns status of each element in the same order.
+    ///
+    /// Files are moved to pool (and duplicates removed) only after elements are committed,
+    /// so files of rolled back elements stay in place
+    pub async fn add_elements<E>(&self, elements: &[E]) -> Result<Vec<AddStatus>, StorageError>
     where E: AsRef<ElementWithMetadata> {
-        let mut hashes = self.get_hashes().await?;
-        let mut o_path = PathBuf::from(&CONFIG.element_pool.path);
-        let mut count = 0;
+        let mut statuses = Vec::with_capacity(elements.len());
+        let mut tx = self.pool.begin().await?;
         
         for elem in elements {
             let ElementWithMetadata(e, ..) = elem.as_ref();
 
             // Deduplication
-            if hashes.contains(&e.hash) {
+            if let Some(id) = Self::get_element_id_by_hash_tx(&mut tx, &e.hash).await? {
                 warn!(name=e.orig_filename, "duplicate, discarding");
-                
-                // Remove duplicated file if not in testing mode
-                if !CONFIG.testing_mode {
-                    std::fs::remove_file(&e.path).ok();
-                }
+                statuses.push(AddStatus::Duplicate(id));
                 continue;
             }
             
-            let mut tx = self.pool.begin().await?;
+            // Failed element will be rolled back to savepoint without aborting whole batch
+            let mut sp = tx.begin().await?;
         
-            let id = match Self::add_element_tx(&mut tx, elem.as_ref()).await {
-                Ok(id) => id,
+            let added = async {
+                let id = Self::add_element_tx(&mut sp, elem.as_ref()).await?;
+
+                // Add tags derived from path to file and from caption file
+                let mut tags = util::get_tags_from_path(&e.path);
+                if let Some(sidecar) = &e.sidecar {
+                    tags.extend(sidecar.tags());
+                }
+                if !tags.is_empty() {
+                    Self::add_tags_tx(&mut sp, Some(id), None, tags.as_slice()).await?;
+                }
+
+                Ok::<_, StorageError>(id)
+            }.await;
+
+            match added {
+                Ok(id) => {
+                    sp.commit().await?;
+                    statuses.push(AddStatus::Added(id));
+                },
                 Err(err) => {
                     error!(?err, name=e.orig_filename, "failed to add element");
-                    continue;
+                    sp.rollback().await?;
+                    statuses.push(AddStatus::Failed(err));
                 },
-            }; 
-            
-            // Add tags derived from path to file
-            let tags = util::get_tags_from_path(&e.path);
-            if !tags.is_empty() {
-                Self::add_tags_tx(&mut tx, Some(id), tags.as_slice()).await?;
             }
-        
-            o_path.push(&e.filename);
-            
-            // Move or copy elements
-            if let Err(err) = if CONFIG.testing_mode {
-                std::fs::copy(&e.path, &o_path).map(|_| ())
-            } else {
-                std::fs::rename(&e.path, &o_path)
-            } {
-                error!(?err, name=e.orig_filename, "failed to move file"); 
-                o_path.pop();
-                continue;
-            }; 
-            
-            o_path.pop();
+        }
 
-            tx.commit().await?;
-            // Add recently inserted hash
-            hashes.push(e.hash);
+        tx.commit().await?;
+
+        // Elements which files failed to move to pool
+        let mut orphans = vec![];
 
-            count += 1;
+        for (elem, status) in elements.iter().zip(statuses.iter_mut()) {
+            let ElementWithMetadata(e, ..) = elem.as_ref();
+
+            match status {
+                // Remove duplicated file if not in testing mode
+                AddStatus::Duplicate(_) if !CONFIG.testing_mode => {
+                    std::fs::remove_file(&e.path).ok();
+                },
+                AddStatus::Added(id) => {
+                    let o_path = util::element_path(&e.filename);
+
+                    // Move or copy elements
+                    let res = o_path
+                        .parent()
+                        .map_or(Ok(()), std::fs::create_dir_all)
+                        .and_then(|_| if CONFIG.testing_mode {
+                            std::fs::copy(&e.path, &o_path).map(|_| ())
+                        } else {
+                            util::move_file(&e.path, &o_path)
+                        });
+
+                    if let Err(err) = res {
+                        error!(?err, name=e.orig_filename, "failed to move file"); 
+                        orphans.push(*id);
+                        *status = AddStatus::Failed(err.into());
+                        continue;
+                    }
+                },
+                _ => continue,
+            }
+
+            // Caption is stored in DB now
+            if !CONFIG.testing_mode {
+                e.remove_sidecar();
+            }
+        }
+
+        if !orphans.is_empty() {
+            for id in orphans {
+                sqlx::query!("DELETE FROM element WHERE id = ?", id)
+                    .execute(&self.pool)
+                    .await?;
+            }
+            // Tags of removed elements were counted
+            self.update_tag_count().await?;
         }
         
         // Invalidate element id cache
         self.id_cache.invalidate_all();
  
-        Ok(count)
+        Ok(statuses)
     }
 
-    /// Get all files' hashes
-    pub async fn get_hashes(&self) -> Result<Vec<Md5Hash>, StorageError> {
-        let hashes = sqlx::query!(
-            r#"SELECT hash FROM element"#
+    /// Get id of element with this hash
+    pub async fn get_element_id_by_hash(&self, hash: &Md5Hash) -> Result<Option<u32>, StorageError> {
+        let mut conn = self.pool.acquire().await?;
+        Self::get_element_id_by_hash_tx(&mut conn, hash).await
+    }
+
+    /// Get id of element with this sha256 hash
+    pub async fn get_element_id_by_sha256(&self, hash: &Sha256Hash) -> Result<Option<u32>, StorageError> {
+        let hash = hash.as_slice();
+        let id = sqlx::query_scalar!(
+            r#"SELECT id as "id!: u32" FROM element WHERE sha256 = ?"#,
+            hash
         )
-        .map(|anon| anon.hash.try_into().unwrap())
-        .fetch_all(&self.pool)
+        .fetch_optional(&self.pool)
         .await?;
-        
-        Ok(hashes)
+
+        Ok(id)
     }
 
     /// Add all tags from slice
@@ -536,7 +893,7 @@ impl Sqlite {
     ) -> Result<(), StorageError>
     where T: AsRef<write::Tag> {
         let mut conn = self.pool.acquire().await?;
-        Self::add_tags_tx(&mut conn, element_id, tags).await?;
+        Self::add_tags_tx(&mut conn, element_id, None, tags).await?;
 
         if element_id.is_some() {
             // Invalidate element id cache
@@ -719,15 +1076,24 @@ impl Sqlite {
             return Ok(None)
         };
 
-        let (add_time, file_time) = sqlx::query!( // sql
+        let (add_time, file_time, md5, sha256, orig_path) = sqlx::query!( // sql
             r#"SELECT 
                 add_time as "add_time!: UtcDateTime", 
-                file_time as "file_time?: UtcDateTime"
+                file_time as "file_time?: UtcDateTime",
+                hash,
+                sha256,
+                orig_path
             FROM element
             WHERE id = ?"#,
             id
         )
-        .map(|anon| (anon.add_time, anon.file_time))
+        .map(|anon| (
+            anon.add_time, 
+            anon.file_time, 
+            util::AsHex(&anon.hash).to_string(),
+            anon.sha256.map(|h| util::AsHex(&h).to_string()),
+            anon.orig_path,
+        ))
         .fetch_one(&self.pool)
         .await?;
 
@@ -750,11 +1116,24 @@ impl Sqlite {
         .fetch_all(&self.pool)
         .await?;
 
+        let neg_tags = sqlx::query_as( // sql
+            "SELECT t.*
+            FROM tag t, element_neg_tag nt
+            WHERE t.id = nt.tag_id AND nt.element_id = ?"
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+
         let meta = read::ElementMetadata {
             ext_meta,
             add_time,
             file_time,
+            md5,
+            sha256,
+            orig_path,
             tags,
+            neg_tags,
         };
         
         Ok(Some((elem, meta)))
@@ -1051,7 +1430,7 @@ impl Sqlite {
                 let Some(alias) = write::Tag::new(to_name, None, tag.tag_type) else {
                     anyhow::bail!("expected alias name");
                 };
-                Self::add_tags_tx(&mut tx, None, &[alias]).await?;
+                Self::add_tags_tx(&mut tx, None, None, &[alias]).await?;
                 // If add_tags suceeded, tag should be present
                 Self::get_tag_data_tx(&mut tx, to_name).await?.unwrap()
             } 
@@ -1165,7 +1544,7 @@ impl Sqlite {
             .map(|d| &d.0)
             .collect();
 
-        Self::add_tags_tx(&mut tx, None, &tags).await?;
+        Self::add_tags_tx(&mut tx, None, None, &tags).await?;
         
         for (tag, aliases) in data {
             Self::add_tag_aliases_tx(&mut tx, tag.name(), aliases).await?;
@@ -1176,6 +1555,353 @@ impl Sqlite {
         Ok(())
     }
 
+    /// Add models to registry, or add new hashes to existing ones
+    pub async fn add_models(&self, models: &[Model]) -> Result<(), StorageError> {
+        let mut tx = self.pool.begin().await?;
+
+        for model in models {
+            let id = sqlx::query_scalar!(
+                r#"INSERT INTO model (name, model_type, base_model)
+                VALUES (?, ?, ?)
+                ON CONFLICT (name, model_type) 
+                DO UPDATE SET base_model = coalesce(excluded.base_model, base_model)
+                RETURNING id as "id!: u32""#,
+                model.name,
+                model.model_type,
+                model.base_model
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+
+            for (kind, hash) in &model.hashes {
+                sqlx::query!(
+                    "INSERT OR REPLACE INTO model_hash (model_id, kind, hash)
+                    VALUES (?, ?, ?)",
+                    id,
+                    kind,
+                    hash
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Get AutoV1 hashes of models that have full SHA-256 hash.
+    /// Model file doesn't need to be hashed again if its AutoV1 hash is the same
+    pub async fn get_hashed_models(&self) -> Result<HashMap<(String, ModelType), String>, StorageError> {
+        let models = sqlx::query!(
+            r#"SELECT m.name, m.model_type as "model_type: ModelType", v1.hash
+            FROM model m
+            JOIN model_hash h ON h.model_id = m.id AND h.kind = ?
+            JOIN model_hash v1 ON v1.model_id = m.id AND v1.kind = ?"#,
+            HashKind::Sha256,
+            HashKind::AutoV1
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|r| ((r.name, r.model_type), r.hash))
+        .collect();
+
+        Ok(models)
+    }
+
+    /// Add tags of registry models to all elements with generation parameters.
+    /// Tag counts should be updated after this.
+    /// Returns count of elements that got tags
+    pub async fn tag_models(&self) -> Result<u32, StorageError> {
+        let mut tx = self.pool.begin().await?;
+
+        let rows = sqlx::query!(
+            r#"SELECT 
+                element_id as "element_id!: u32", 
+                importer_id as "source: MetadataSource", 
+                raw_meta as "raw_meta!"
+            FROM metadata 
+            WHERE raw_meta IS NOT NULL"#
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut count = 0;
+        for row in rows {
+            let Some(params) = row.source.generation_params(&row.raw_meta) else {
+                continue
+            };
+
+            let models = Self::resolve_models_tx(&mut tx, &params).await?;
+            if !models.is_empty() {
+                Self::add_tags_tx(&mut tx, Some(row.element_id), Some(row.source), &models).await?;
+                count += 1;
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(count)
+    }
+
+    /// Get `(id, filename)` of elements to re-parse, optionally limited by search query
+    /// and by source of element metadata
+    pub async fn get_reparse_targets(
+        &self,
+        query: Option<&str>,
+        source: Option<MetadataSource>
+    ) -> Result<Vec<(u32, String)>, StorageError> {
+        let mut conn = self.pool.acquire().await?;
+
+        let ids = match query {
+            Some(query) => Some(
+                Self::get_element_ids_by_query_tx(&mut conn, query)
+                    .await?
+                    .into_iter()
+                    .collect::<HashSet<_>>()
+            ),
+            None => None,
+        };
+
+        let targets = sqlx::query!(
+            r#"SELECT id as "id!: u32", filename FROM element
+            WHERE ? IS NULL OR id IN (SELECT element_id FROM metadata WHERE importer_id = ?)
+            ORDER BY id"#,
+            source,
+            source
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .filter(|row| ids.as_ref().is_none_or(|ids| ids.contains(&row.id)))
+        .map(|row| (row.id, row.filename))
+        .collect();
+
+        Ok(targets)
+    }
+
+    /// Replace metadata parsed from element file, along with tags added by parsers.
+    /// Manually added tags and metadata from fetchers are kept.
+    /// Tag counts should be updated after this
+    pub async fn replace_parsed_metadata(
+        &self,
+        element_id: u32,
+        source: MetadataSource,
+        meta: &write::ElementMetadata
+    ) -> Result<(), StorageError> {
+        let mut tx = self.pool.begin().await?;
+
+        // Importer ids are integers, so they're formatted directly
+        let sources = enum_iterator::all::<Parser>()
+            .map(|p| p.metadata_source() as u8)
+            .join(", ");
+
+        sqlx::query(&format!( // sql
+            "DELETE FROM metadata WHERE element_id = ? AND importer_id IN ({sources})"
+        ))
+        .bind(element_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(&format!( // sql
+            "DELETE FROM element_tag WHERE element_id = ? AND source IN ({sources})"
+        ))
+        .bind(element_id)
+        .execute(&mut *tx)
+        .await?;
+
+        // Negative prompt tags and generation parameters come only from parsers
+        sqlx::query!("DELETE FROM element_neg_tag WHERE element_id = ?", element_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!("DELETE FROM generation_params WHERE element_id = ?", element_id)
+            .execute(&mut *tx)
+            .await?;
+
+        Self::add_metadata_tx(&mut tx, element_id, source, meta).await?;
+
+        tx.commit().await?;
+
+        // Invalidate element id cache
+        self.id_cache.invalidate_all();
+
+        Ok(())
+    }
+
+    /// Record import failure of file at `path`.
+    /// Repeated failures of the same file update existing record
+    pub async fn add_import_failure(
+        &self, 
+        path: &str, 
+        orig_path: &str, 
+        error: &str
+    ) -> Result<(), StorageError> {
+        sqlx::query!(
+            "INSERT INTO import_failure (path, orig_path, error) 
+            VALUES (?, ?, ?)
+            ON CONFLICT (path) DO UPDATE 
+            SET error = excluded.error, fail_time = CURRENT_TIMESTAMP",
+            path,
+            orig_path,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get all import failures, most recent first
+    pub async fn get_import_failures(&self) -> Result<Vec<ImportFailure>, StorageError> {
+        let failures = sqlx::query_as!(
+            ImportFailure,
+            r#"SELECT 
+                id as "id!: u32", 
+                path, 
+                orig_path, 
+                error, 
+                fail_time as "fail_time!: UtcDateTime"
+            FROM import_failure
+            ORDER BY fail_time DESC, id DESC"#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(failures)
+    }
+
+    /// Get import failure by id
+    pub async fn get_import_failure(&self, id: u32) -> Result<Option<ImportFailure>, StorageError> {
+        let failure = sqlx::query_as!(
+            ImportFailure,
+            r#"SELECT 
+                id as "id!: u32", 
+                path, 
+                orig_path, 
+                error, 
+                fail_time as "fail_time!: UtcDateTime"
+            FROM import_failure
+            WHERE id = ?"#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(failure)
+    }
+
+    /// Remove import failure record
+    pub async fn remove_import_failure(&self, id: u32) -> Result<(), StorageError> {
+        sqlx::query!(
+            "DELETE FROM import_failure WHERE id = ?",
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Add finished import run to history
+    pub async fn add_import_run(
+        &self,
+        trigger: ImportTrigger,
+        start_time: UtcDateTime,
+        end_time: UtcDateTime,
+        stats: &ImportStats,
+        error: Option<&str>,
+    ) -> Result<(), StorageError> {
+        sqlx::query!(
+            "INSERT INTO import_run (
+                trigger, start_time, end_time, files_seen, imported, duplicates, failed, 
+                metadata_fetched, groups_formed, thumbnails_made, error
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            trigger,
+            start_time,
+            end_time,
+            stats.files_seen,
+            stats.imported,
+            stats.duplicates,
+            stats.failed,
+            stats.metadata_fetched,
+            stats.groups_formed,
+            stats.thumbnails_made,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get up to `limit` most recent import runs
+    pub async fn get_import_history(&self, limit: u32) -> Result<Vec<ImportRun>, StorageError> {
+        let runs = sqlx::query!(
+            r#"SELECT 
+                id as "id!: u32",
+                trigger as "trigger!: ImportTrigger",
+                start_time as "start_time!: UtcDateTime",
+                end_time as "end_time!: UtcDateTime",
+                files_seen as "files_seen!: u32",
+                imported as "imported!: u32",
+                duplicates as "duplicates!: u32",
+                failed as "failed!: u32",
+                metadata_fetched as "metadata_fetched!: u32",
+                groups_formed as "groups_formed!: u32",
+                thumbnails_made as "thumbnails_made!: u32",
+                error
+            FROM import_run
+            ORDER BY id DESC
+            LIMIT ?"#,
+            limit
+        )
+        .map(|anon| ImportRun {
+            id: anon.id,
+            trigger: anon.trigger,
+            start_time: anon.start_time,
+            end_time: anon.end_time,
+            stats: ImportStats {
+                files_seen: anon.files_seen,
+                imported: anon.imported,
+                duplicates: anon.duplicates,
+                failed: anon.failed,
+                metadata_fetched: anon.metadata_fetched,
+                groups_formed: anon.groups_formed,
+                thumbnails_made: anon.thumbnails_made,
+            },
+            error: anon.error,
+        })
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(runs)
+    }
+
+    /// Get nested collections of collection at `parent` path, or top-level ones if `None`
+    pub async fn get_collections(&self, parent: Option<&str>) -> Result<Vec<Collection>, StorageError> {
+        // Range on `orig_path` matches paths with `<path>/` prefix literally
+        // and uses index; `0` is the next char after `/`
+        let collections = sqlx::query_as!(
+            Collection,
+            r#"SELECT c.path, count(e.id) as "element_count!: u32" 
+            FROM collection c
+            LEFT JOIN element e 
+                ON e.orig_path >= c.path || '/' AND e.orig_path < c.path || '0'
+            WHERE c.parent_id IS (SELECT id FROM collection WHERE path = ?)
+            GROUP BY c.id
+            ORDER BY c.path"#,
+            parent
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(collections)
+    }
+
     /// Loads tag aliases to memory in order to speed up multiple lookups 
     pub async fn reload_tag_aliases_index(&self) -> Result<(), StorageError> {
         let mut stream = sqlx::query!(
@@ -0,0 +1,299 @@
+//! ComfyUI
+//!
+//! https://github.com/comfyanonymous/ComfyUI
+//!
+//! Node graph in API format is stored in PNG `prompt` text chunk,
+//! editor representation of the same graph is in `workflow` chunk.
+//! Only `prompt` is needed to get parameters, as it contains executed nodes only
+use std::collections::{HashSet, VecDeque};
+
+use anyhow::Context;
+use itertools::Itertools;
+use nndb_common::metadata::comfyui::{Metadata, Lora};
+use serde_json::{Map, Value};
+
+use crate::model::write::ElementMetadata;
+
+use super::{ElementPrefab, Parser, png_text, prompt_tags};
+
+/// Node id -> node (`class_type` and `inputs`)
+type Graph = Map<String, Value>;
+
+/// Classes of sampler nodes
+const SAMPLERS: &[&str] = &["KSampler", "KSamplerAdvanced", "SamplerCustom", "SamplerCustomAdvanced"];
+
+/// Text inputs of prompt encoders
+const TEXT_INPUTS: &[&str] = &["text", "text_g", "text_l", "clip_l", "t5xxl"];
+
+/// Inputs that are not followed when looking for sampler parameters,
+/// as they lead to previous stages of generation
+const SKIP_INPUTS: &[&str] = &[
+    "model", "clip", "vae", "positive", "negative",
+    "conditioning", "latent_image", "image"
+];
+
+/// Max depth of links resolved to get a single value
+const MAX_DEPTH: usize = 4;
+
+/// Parse node graph, check that it looks like ComfyUI one
+fn get_graph(element: &ElementPrefab) -> Option<Graph> {
+    let graph: Graph = serde_json::from_str(&png_text(element, "prompt")?).ok()?;
+    graph
+        .values()
+        .any(|n| class(n).is_some())
+        .then_some(graph)
+}
+
+fn class(node: &Value) -> Option<&str> {
+    node.get("class_type")?.as_str()
+}
+
+fn inputs(node: &Value) -> Option<&Map<String, Value>> {
+    node.get("inputs")?.as_object()
+}
+
+/// Get `(node_id, output_index)` if value is a link to other node's output
+fn link(value: &Value) -> Option<(&str, u64)> {
+    match value.as_array()?.as_slice() {
+        [id, idx] => Some((id.as_str()?, idx.as_u64()?)),
+        _ => None
+    }
+}
+
+/// Iterate over nodes that are linked to inputs of the node (except skipped ones)
+fn upstream<'a>(graph: &'a Graph, id: &str) -> impl Iterator<Item = &'a str> {
+    graph
+        .get(id)
+        .and_then(inputs)
+        .into_iter()
+        .flatten()
+        .filter(|(k, _)| !SKIP_INPUTS.contains(&k.as_str()))
+        .filter_map(|(_, v)| link(v).map(|(id, _)| id))
+}
+
+/// Walk nodes linked to `start`, breadth-first
+fn walk<'a>(graph: &'a Graph, start: &'a str) -> impl Iterator<Item = &'a str> {
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::from([start]);
+
+    std::iter::from_fn(move || {
+        let id = queue.pop_front()?;
+        queue.extend(upstream(graph, id).filter(|id| visited.insert(*id)));
+        Some(id)
+    })
+    .take(graph.len())
+}
+
+/// Find sampler of the first generation pass,
+/// i.e. without other samplers before it
+fn find_sampler(graph: &Graph) -> Option<&str> {
+    let is_sampler = |id: &str| graph
+        .get(id)
+        .and_then(class)
+        .is_some_and(|c| SAMPLERS.contains(&c));
+
+    // Latent image is skipped by walk, so follow it separately
+    let prev_sampler = |id: &str| graph
+        .get(id)
+        .and_then(inputs)
+        .and_then(|i| i.get("latent_image"))
+        .and_then(link)
+        .is_some_and(|(prev, _)| walk(graph, prev).any(is_sampler));
+
+    graph
+        .keys()
+        .map(String::as_str)
+        .filter(|id| is_sampler(id))
+        .sorted_by_key(|id| (id.len(), *id))
+        .find(|id| !prev_sampler(id))
+}
+
+/// Find input value by one of names, starting from the sampler.
+/// Links to primitive nodes are resolved
+fn find_input<'a>(graph: &'a Graph, sampler: &'a str, keys: &[&str]) -> Option<&'a Value> {
+    walk(graph, sampler)
+        .filter_map(|id| graph.get(id).and_then(inputs))
+        .find_map(|inputs| keys.iter().find_map(|k| inputs.get(*k)))
+        .and_then(|v| resolve_value(graph, v, 0))
+}
+
+/// Resolve link to scalar value
+fn resolve_value<'a>(graph: &'a Graph, value: &'a Value, depth: usize) -> Option<&'a Value> {
+    let Some((id, _)) = link(value) else {
+        return Some(value)
+    };
+
+    if depth >= MAX_DEPTH {
+        return None
+    }
+
+    graph
+        .get(id)
+        .and_then(inputs)?
+        .values()
+        .find_map(|v| resolve_value(graph, v, depth + 1))
+        .filter(|v| !v.is_array())
+}
+
+/// Collect texts of prompt encoders feeding conditioning link
+fn collect_texts(graph: &Graph, value: &Value, visited: &mut HashSet<String>, out: &mut Vec<String>) {
+    let Some((id, output)) = link(value) else { return };
+    if !visited.insert(id.to_owned()) {
+        return
+    }
+
+    let Some(node) = graph.get(id) else { return };
+    let Some(inputs) = inputs(node) else { return };
+
+    if class(node).is_some_and(|c| c.contains("TextEncode")) {
+        let texts = TEXT_INPUTS
+            .iter()
+            .filter_map(|k| inputs.get(*k))
+            .filter_map(|v| resolve_value(graph, v, 0)?.as_str())
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::to_owned);
+        out.extend(texts);
+        return
+    }
+
+    // Node like ControlNetApplyAdvanced, that outputs both positive and negative
+    if let (Some(pos), Some(neg)) = (inputs.get("positive"), inputs.get("negative")) {
+        let next = if output == 0 { pos } else { neg };
+        collect_texts(graph, next, visited, out);
+        return
+    }
+
+    // Conditioning combine, concat, etc
+    for (_, v) in inputs.iter().filter(|(k, _)| k.contains("conditioning")) {
+        collect_texts(graph, v, visited, out);
+    }
+}
+
+/// Get prompt feeding conditioning input of the sampler (or the guider)
+fn get_prompt_text(graph: &Graph, sampler: &str, keys: &[&str]) -> String {
+    let mut texts = vec![];
+
+    let conditioning = walk(graph, sampler)
+        .filter_map(|id| graph.get(id).and_then(inputs))
+        .find_map(|inputs| keys.iter().find_map(|k| inputs.get(*k)));
+
+    if let Some(value) = conditioning {
+        collect_texts(graph, value, &mut HashSet::new(), &mut texts);
+    }
+
+    // SDXL encoders usually have the same text in `text_g` and `text_l`
+    texts.into_iter().unique().join(", ")
+}
+
+/// Follow model chain from the sampler, collecting LoRAs and checkpoint
+fn get_models(graph: &Graph, sampler: &str) -> (Option<String>, Vec<Lora>) {
+    let mut checkpoint = None;
+    let mut loras = vec![];
+
+    let mut model = walk(graph, sampler)
+        .filter_map(|id| graph.get(id).and_then(inputs))
+        .find_map(|inputs| inputs.get("model"));
+
+    for _ in 0..graph.len() {
+        let Some(inputs) = model
+            .and_then(link)
+            .and_then(|(id, _)| graph.get(id))
+            .and_then(inputs) else { break };
+
+        let string = |key: &str| inputs
+            .get(key)
+            .and_then(|v| resolve_value(graph, v, 0))
+            .and_then(Value::as_str)
+            .map(str::to_owned);
+
+        if let Some(name) = string("lora_name") {
+            loras.push(Lora {
+                name,
+                strength: inputs.get("strength_model").and_then(Value::as_f64)
+            });
+        }
+
+        if let Some(name) = string("ckpt_name").or_else(|| string("unet_name")) {
+            checkpoint = Some(name);
+            break;
+        }
+
+        model = inputs.get("model");
+    }
+
+    // Loaders are walked from the sampler, so reverse to get application order
+    loras.reverse();
+
+    (checkpoint, loras)
+}
+
+/// Get dimension of latent image fed to the sampler
+fn latent_size(graph: &Graph, sampler: &str, key: &str) -> Option<u32> {
+    let (id, _) = graph
+        .get(sampler)
+        .and_then(inputs)?
+        .get("latent_image")
+        .and_then(link)?;
+
+    graph
+        .get(id)
+        .and_then(inputs)?
+        .get(key)
+        .and_then(|v| resolve_value(graph, v, 0))?
+        .as_u64()
+        .map(|v| v as u32)
+}
+
+/// Check if element contains ComfyUI node graph
+pub fn can_parse(element: &ElementPrefab) -> bool {
+    get_graph(element).is_some()
+}
+
+pub fn extract_metadata(
+    element: &ElementPrefab
+) -> anyhow::Result<ElementMetadata> {
+    let graph = get_graph(element).context("`prompt` node graph not found")?;
+    let sampler = find_sampler(&graph).context("sampler node not found")?.to_owned();
+    let sampler = sampler.as_str();
+
+    let prompt = get_prompt_text(&graph, sampler, &["positive", "conditioning"]);
+    let negative_prompt = get_prompt_text(&graph, sampler, &["negative"]);
+    let (checkpoint, loras) = get_models(&graph, sampler);
+
+    let meta = Metadata {
+        seed: find_input(&graph, sampler, &["seed", "noise_seed"]).and_then(Value::as_i64),
+        steps: find_input(&graph, sampler, &["steps"])
+            .and_then(Value::as_u64)
+            .map(|v| v as u32),
+        cfg: find_input(&graph, sampler, &["cfg"]).and_then(Value::as_f64),
+        sampler: find_input(&graph, sampler, &["sampler_name"])
+            .and_then(Value::as_str)
+            .map(str::to_owned),
+        scheduler: find_input(&graph, sampler, &["scheduler"])
+            .and_then(Value::as_str)
+            .map(str::to_owned),
+        denoise: find_input(&graph, sampler, &["denoise"]).and_then(Value::as_f64),
+        width: latent_size(&graph, sampler, "width"),
+        height: latent_size(&graph, sampler, "height"),
+        checkpoint,
+        loras,
+        prompt,
+        negative_prompt,
+        graph: Value::Object(graph),
+    };
+
+    let tags = prompt_tags(&meta.prompt)
+        // Append source tag
+        .chain(Parser::ComfyUI.source_tag())
+        .collect();
+
+    Ok(ElementMetadata {
+        src_link: None,
+        src_time: None,
+        group: meta.seed,
+        raw_meta: Some(serde_json::to_string(&meta)?),
+        tags,
+        neg_tags: prompt_tags(&meta.negative_prompt).collect(),
+    })
+}
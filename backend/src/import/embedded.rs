@@ -0,0 +1,153 @@
+//! Metadata embedded into EXIF and XMP of compressed (JPEG, WebP, etc.) images
+//!
+//! Webui saves generation parameters to EXIF `UserComment`,
+//! other tools write them to XMP packet (`exif:UserComment`, `dc:description`...)
+
+use std::io::Cursor;
+
+use exif::{Exif, In, Tag, Value};
+use once_cell::sync::Lazy;
+use regex::{Regex, bytes};
+
+use super::ElementPrefab;
+
+/// XMP packet, can be found anywhere in file
+static XMP_REX: Lazy<bytes::Regex> = Lazy::new(|| {
+    bytes::Regex::new(r"(?s)<x:xmpmeta.*?</x:xmpmeta>").unwrap()
+});
+
+/// First item of `rdf:Alt`, `rdf:Seq` or `rdf:Bag`
+static RDF_LI_REX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?s)<rdf:li[^>]*>(.*?)</rdf:li>").unwrap()
+});
+
+/// XML character reference `&#10;`, `&#x0A;`
+static CHAR_REF_REX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"&#(x?)([0-9a-fA-F]+);").unwrap()
+});
+
+/// EXIF and XMP of element
+#[derive(Default)]
+pub struct Embedded {
+    exif: Option<Exif>,
+    xmp: Option<String>,
+}
+
+impl Embedded {
+    /// Read EXIF and XMP from element data.
+    /// Elements that weren't loaded to memory (animations) are skipped
+    pub fn read(element: &ElementPrefab) -> Self {
+        let Some(data) = &element.data else {
+            return Self::default()
+        };
+
+        let exif = exif::Reader::new()
+            .read_from_container(&mut Cursor::new(data))
+            .ok();
+        
+        let xmp = XMP_REX
+            .find(data)
+            .map(|m| String::from_utf8_lossy(m.as_bytes()).into_owned());
+
+        Self { exif, xmp }
+    }
+
+    /// Get text of EXIF `UserComment`
+    pub fn user_comment(&self) -> Option<String> {
+        let field = self.exif.as_ref()?.get_field(Tag::UserComment, In::PRIMARY)?;
+        match &field.value {
+            Value::Undefined(bytes, _) => decode_user_comment(bytes),
+            _ => None
+        }
+    }
+
+    /// Get text of EXIF ASCII field, e.g. `Software` or `ImageDescription`.
+    /// Non-ASCII contents are decoded as UTF-8
+    pub fn ascii(&self, tag: Tag) -> Option<String> {
+        let field = self.exif.as_ref()?.get_field(tag, In::PRIMARY)?;
+        match &field.value {
+            Value::Ascii(lines) => Some(
+                lines
+                    .iter()
+                    .map(|l| String::from_utf8_lossy(l))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+            _ => None
+        }
+    }
+
+    /// Get text of XMP property `name` (e.g. `dc:description`). 
+    /// For arrays, first item is returned
+    pub fn xmp_property(&self, name: &str) -> Option<String> {
+        let xmp = self.xmp.as_ref()?;
+        let name = regex::escape(name);
+
+        // Property may be written as attribute or as element
+        let attr = Regex::new(&format!(r#"\s{name}="([^"]*)""#)).ok()?;
+        let elem = Regex::new(&format!(r"(?s)<{name}(?:\s[^>]*)?>(.*?)</{name}>")).ok()?;
+
+        let value = match attr.captures(xmp) {
+            Some(caps) => caps.get(1)?.as_str(),
+            None => {
+                let inner = elem.captures(xmp)?.get(1)?.as_str();
+                match RDF_LI_REX.captures(inner) {
+                    Some(caps) => caps.get(1)?.as_str(),
+                    None => inner,
+                }
+            },
+        };
+
+        Some(unescape_xml(value))
+    }
+}
+
+/// Decode `UserComment` value, that is prefixed with 8-byte charset code
+fn decode_user_comment(bytes: &[u8]) -> Option<String> {
+    if bytes.len() < 8 {
+        return None
+    }
+    let (code, text) = bytes.split_at(8);
+
+    let text = match code {
+        b"UNICODE\0" => {
+            // Endianness isn't specified, webui writes big endian.
+            // Guess it from first char, which is ASCII most of the time
+            let big_endian = text.first() == Some(&0) || text.get(1) != Some(&0);
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|c| match big_endian {
+                    true => u16::from_be_bytes([c[0], c[1]]),
+                    false => u16::from_le_bytes([c[0], c[1]]),
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        },
+        // Undefined charset is usually ASCII or UTF-8 too
+        b"ASCII\0\0\0" | b"\0\0\0\0\0\0\0\0" => String::from_utf8_lossy(text).into_owned(),
+        // JIS is not supported
+        _ => return None
+    };
+
+    let text = text.trim_end_matches('\0');
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+/// Replace XML entities and character references
+fn unescape_xml(text: &str) -> String {
+    let text = CHAR_REF_REX.replace_all(text, |caps: &regex::Captures| {
+        let radix = if caps[1].is_empty() { 10 } else { 16 };
+        u32::from_str_radix(&caps[2], radix)
+            .ok()
+            .and_then(char::from_u32)
+            .map(String::from)
+            .unwrap_or_default()
+    });
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        // Should be the last one, so `&amp;lt;` isn't unescaped twice
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,107 @@
+//! Detection of element file type by content (magic bytes), 
+//! as files often have wrong extension or none at all
+
+use std::{path::Path, io::Read, fs::File};
+
+use image::ImageFormat;
+
+/// Count of bytes, that is enough to detect type
+pub const HEADER_LEN: usize = 256;
+
+/// Supported file type
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FileType {
+    /// MIME type
+    pub mime: &'static str,
+    /// Canonical extension, used in element pool
+    pub ext: &'static str,
+    /// Whether this is animation (video), that is not decoded as image
+    pub animated: bool,
+}
+
+impl FileType {
+    pub const PNG: Self = Self::image("image/png", "png");
+    pub const JPEG: Self = Self::image("image/jpeg", "jpeg");
+    pub const GIF: Self = Self::image("image/gif", "gif");
+    pub const WEBP: Self = Self::image("image/webp", "webp");
+    pub const AVIF: Self = Self::image("image/avif", "avif");
+    pub const MP4: Self = Self::video("video/mp4", "mp4");
+    pub const MOV: Self = Self::video("video/quicktime", "mov");
+    pub const M4V: Self = Self::video("video/x-m4v", "m4v");
+    pub const WEBM: Self = Self::video("video/webm", "webm");
+
+    const fn image(mime: &'static str, ext: &'static str) -> Self {
+        Self { mime, ext, animated: false }
+    }
+
+    const fn video(mime: &'static str, ext: &'static str) -> Self {
+        Self { mime, ext, animated: true }
+    }
+
+    /// Detect type by file header
+    pub fn sniff(header: &[u8]) -> Option<Self> {
+        match header {
+            [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(Self::PNG),
+            [0xFF, 0xD8, 0xFF, ..] => Some(Self::JPEG),
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(Self::GIF),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(Self::WEBP),
+            [_, _, _, _, b'f', b't', b'y', b'p', ..] => sniff_iso_bmff(header),
+            // Matroska, only WebM doctype is supported
+            [0x1A, 0x45, 0xDF, 0xA3, ..] => header
+                .windows(4)
+                .any(|w| w == b"webm")
+                .then_some(Self::WEBM),
+            _ => None
+        }
+    }
+
+    /// Read header of file at `path` and detect its type
+    pub fn detect(path: &Path) -> std::io::Result<Option<Self>> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        File::open(path)?
+            .take(HEADER_LEN as u64)
+            .read_to_end(&mut header)?;
+
+        Ok(Self::sniff(&header))
+    }
+
+    /// Image decoder format, `None` for animations
+    pub fn image_format(&self) -> Option<ImageFormat> {
+        match *self {
+            Self::PNG => Some(ImageFormat::Png),
+            Self::JPEG => Some(ImageFormat::Jpeg),
+            Self::GIF => Some(ImageFormat::Gif),
+            Self::WEBP => Some(ImageFormat::WebP),
+            Self::AVIF => Some(ImageFormat::Avif),
+            _ => None
+        }
+    }
+}
+
+/// Detect type of ISO base media file by brands in `ftyp` box
+fn sniff_iso_bmff(header: &[u8]) -> Option<FileType> {
+    let box_len = u32::from_be_bytes(header.get(0..4)?.try_into().ok()?) as usize;
+    let major = header.get(8..12)?;
+    // Compatible brands follow major brand and minor version
+    let compatible = header
+        .get(16..box_len.min(header.len()))
+        .unwrap_or_default()
+        .chunks_exact(4);
+    let brands: Vec<&[u8]> = std::iter::once(major).chain(compatible).collect();
+
+    let has = |brand: &[u8]| brands.contains(&brand);
+
+    if has(b"avif") || has(b"avis") {
+        Some(FileType::AVIF)
+    } else if major == b"qt  " {
+        Some(FileType::MOV)
+    } else if major.starts_with(b"M4V") {
+        Some(FileType::M4V)
+    } else if has(b"isom") || has(b"iso2") || has(b"mp41") || has(b"mp42") 
+        || has(b"avc1") || has(b"dash") || has(b"MSNV") {
+        Some(FileType::MP4)
+    } else {
+        // HEIC and others are not supported
+        None
+    }
+}
@@ -0,0 +1,48 @@
+//! Fooocus
+//!
+//! https://github.com/lllyasviel/Fooocus
+//!
+//! With `fooocus` metadata scheme parameters are stored as JSON in PNG `parameters` 
+//! text chunk, or in EXIF `UserComment` of JPEG and WebP.
+//! Outputs with `a1111` scheme are handled by webui parser
+use anyhow::Context;
+use nndb_common::metadata::fooocus::Metadata;
+
+use crate::model::write::ElementMetadata;
+
+use super::{ElementPrefab, Parser, is_png, png_text, embedded::Embedded, prompt_tags};
+
+/// Find metadata JSON that can be parsed
+fn get_metadata(element: &ElementPrefab) -> Option<(String, Metadata)> {
+    let raw = match is_png(element) {
+        true => png_text(element, "parameters"),
+        false => Embedded::read(element).user_comment(),
+    }?;
+
+    Metadata::parse(&raw).map(|meta| (raw, meta))
+}
+
+/// Check if importer can get metadata for element
+pub fn can_parse(element: &ElementPrefab) -> bool {
+    get_metadata(element).is_some()
+}
+
+pub fn extract_metadata(
+    element: &ElementPrefab
+) -> anyhow::Result<ElementMetadata> {
+    let (raw, meta) = get_metadata(element).context("Fooocus metadata not found")?;
+
+    let tags = prompt_tags(&meta.prompt)
+        // Append source tag
+        .chain(Parser::Fooocus.source_tag())
+        .collect();
+
+    Ok(ElementMetadata {
+        src_link: None,
+        src_time: None,
+        group: meta.seed,
+        raw_meta: Some(raw),
+        tags,
+        neg_tags: prompt_tags(&meta.negative_prompt).collect(),
+    })
+}
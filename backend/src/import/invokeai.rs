@@ -0,0 +1,48 @@
+//! InvokeAI
+//!
+//! https://github.com/invoke-ai/InvokeAI
+//!
+//! Parameters are stored as JSON in PNG `invokeai_metadata` text chunk (v3 and later),
+//! or in `sd-metadata` chunk (v2)
+use anyhow::Context;
+use nndb_common::metadata::invokeai::Metadata;
+
+use crate::model::write::ElementMetadata;
+
+use super::{ElementPrefab, Parser, png_text, prompt_tags};
+
+/// Text chunks with metadata, in order of priority
+const CHUNKS: &[&str] = &["invokeai_metadata", "sd-metadata"];
+
+/// Find metadata JSON that can be parsed
+fn get_metadata(element: &ElementPrefab) -> Option<(String, Metadata)> {
+    CHUNKS
+        .iter()
+        .filter_map(|key| png_text(element, key))
+        .find_map(|raw| Metadata::parse(&raw).map(|meta| (raw, meta)))
+}
+
+/// Check if importer can get metadata for element
+pub fn can_parse(element: &ElementPrefab) -> bool {
+    get_metadata(element).is_some()
+}
+
+pub fn extract_metadata(
+    element: &ElementPrefab
+) -> anyhow::Result<ElementMetadata> {
+    let (raw, meta) = get_metadata(element).context("InvokeAI metadata not found")?;
+
+    let tags = prompt_tags(&meta.prompt)
+        // Append source tag
+        .chain(Parser::InvokeAI.source_tag())
+        .collect();
+
+    Ok(ElementMetadata {
+        src_link: None,
+        src_time: None,
+        group: meta.seed,
+        raw_meta: Some(raw),
+        tags,
+        neg_tags: prompt_tags(&meta.negative_prompt).collect(),
+    })
+}
@@ -1,15 +1,26 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, io::{Read, Cursor, BufReader}, fs::File, sync::OnceLock};
 
-use crate::model::{write::{ElementMetadata, Tag}, read::PendingImport};
+use crate::{model::{write::{ElementMetadata, Tag}, read::PendingImport}, dao::STORAGE};
 use enum_iterator::Sequence;
-use nndb_common::{MetadataSource, TagType};
+use nndb_common::{
+    MetadataSource, TagType, 
+    metadata::{self, prompt::{parse_prompt, TermKind}}, 
+    webui::iter_metadata
+};
 
 mod novelai;
 mod webui;
+mod comfyui;
+mod invokeai;
+mod fooocus;
 mod pixiv;
+mod sidecar;
+mod embedded;
+mod file_type;
+mod stealth;
 
-pub const IMAGE_EXTS: &[&str] = &["png", "jpeg", "jpg", "gif", "avif", "webp"];
-pub const ANIMATION_EXTS: &[&str] = &["mp4", "mov", "webm", "m4v"];
+pub use sidecar::{Sidecar, sidecar_tags};
+pub use file_type::FileType;
 
 /// Name directory as `TAG.<tag_type>.<tag_name>.<tag_type>.<tag_name>...`
 /// to add `<tag_name>...` to elements in this directory 
@@ -18,10 +29,48 @@ pub const TAG_TRIGGER: &str = "TAG.";
 /// Holder with element original filename and data 
 pub struct ElementPrefab {
     pub path: PathBuf,
-    pub data: Vec<u8>,
+    /// File data, if it was loaded to memory.
+    /// Otherwise file will be streamed from disk
+    pub data: Option<Vec<u8>>,
+    /// Type detected by file content
+    pub file_type: FileType,
+    /// Stealth metadata, decoded on first read
+    stealth: OnceLock<Option<String>>,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, sqlx::Type)]
+impl ElementPrefab {
+    /// Read image to memory. 
+    /// Animations are not loaded, as they may be very large and 
+    /// all their data is needed only for hashing.
+    ///
+    /// Images are read whole, as they're decoded anyway to make signature.
+    /// Parsers read metadata from the same buffer
+    ///
+    /// Fails if file type is not supported
+    pub fn load(path: PathBuf) -> std::io::Result<Self> {
+        let file_type = FileType::detect(&path)?.ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::InvalidData, 
+            "unsupported file type"
+        ))?;
+
+        let data = match file_type.animated {
+            true => None,
+            false => Some(std::fs::read(&path)?)
+        };
+
+        Ok(Self { path, data, file_type, stealth: OnceLock::new() })
+    }
+
+    /// Get reader over file data
+    pub fn reader(&self) -> std::io::Result<Box<dyn Read + '_>> {
+        Ok(match &self.data {
+            Some(data) => Box::new(Cursor::new(data)),
+            None => Box::new(BufReader::new(File::open(&self.path)?)),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, sqlx::Type, Sequence)]
 #[repr(u8)]
 pub enum Parser {
     /// No specific metadata
@@ -30,6 +79,12 @@ pub enum Parser {
     NovelAI     = 1,
     // Webui generations
     Webui       = 2,
+    // ComfyUI generations
+    ComfyUI     = 4,
+    // InvokeAI generations
+    InvokeAI    = 5,
+    // Fooocus generations
+    Fooocus     = 6,
 }
 
 impl Parser {
@@ -37,6 +92,9 @@ impl Parser {
     pub fn scan(element: &ElementPrefab) -> Self  {
         match () {
             _ if webui::can_parse(element) => Self::Webui,
+            _ if comfyui::can_parse(element) => Self::ComfyUI,
+            _ if invokeai::can_parse(element) => Self::InvokeAI,
+            _ if fooocus::can_parse(element) => Self::Fooocus,
             _ if novelai::can_parse(element) => Self::NovelAI,
             _ => Self::Passthrough
         }
@@ -48,9 +106,39 @@ impl Parser {
             Parser::Passthrough => MetadataSource::Passthrough,
             Parser::NovelAI => MetadataSource::NovelAI,
             Parser::Webui => MetadataSource::Webui,
+            Parser::ComfyUI => MetadataSource::ComfyUI,
+            Parser::InvokeAI => MetadataSource::InvokeAI,
+            Parser::Fooocus => MetadataSource::Fooocus,
         }
     }
 
+    /// Get parser that produces metadata of the source, if it is parsed from file
+    pub fn from_source(source: MetadataSource) -> Option<Self> {
+        let parser = match source {
+            MetadataSource::Passthrough => Parser::Passthrough,
+            MetadataSource::NovelAI => Parser::NovelAI,
+            MetadataSource::Webui => Parser::Webui,
+            MetadataSource::ComfyUI => Parser::ComfyUI,
+            MetadataSource::InvokeAI => Parser::InvokeAI,
+            MetadataSource::Fooocus => Parser::Fooocus,
+            _ => return None
+        };
+        Some(parser)
+    }
+
+    /// Metadata tag added to every element parsed by this parser
+    pub fn source_tag(&self) -> Option<Tag> {
+        let name = match self {
+            Parser::Passthrough => "unknown_source",
+            Parser::NovelAI => "novelai_generated",
+            Parser::Webui => "webui_generated",
+            Parser::ComfyUI => "comfyui_generated",
+            Parser::InvokeAI => "invokeai_generated",
+            Parser::Fooocus => "fooocus_generated",
+        };
+        Tag::new(name, None, TagType::Metadata)
+    }
+
     /// Extract metadata on hash deriving stage, provided access to file data
     pub fn extract_metadata(
         &self,
@@ -62,10 +150,14 @@ impl Parser {
                 src_time: None,
                 raw_meta: None,
                 group: None,
-                tags: vec![Tag::new("unknown_source", None, TagType::Metadata).unwrap()],
+                tags: Parser::Passthrough.source_tag().into_iter().collect(),
+                neg_tags: vec![],
             }),
             Parser::NovelAI => novelai::extract_metadata(element),
             Parser::Webui => webui::extract_metadata(element),
+            Parser::ComfyUI => comfyui::extract_metadata(element),
+            Parser::InvokeAI => invokeai::extract_metadata(element),
+            Parser::Fooocus => fooocus::extract_metadata(element),
         }
     } 
 }
@@ -118,9 +210,124 @@ pub enum FetchStatus {
 
 // Check PNG header
 fn is_png(element: &ElementPrefab) -> bool {
-    element.data.starts_with(
-        &[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A]
-    )    
+    element.file_type == FileType::PNG
+}
+
+/// Get PNG text chunk by keyword (tEXt, or iTXt for non-latin1 text)
+fn png_text(element: &ElementPrefab, keyword: &str) -> Option<String> {
+    if !is_png(element) {
+        return None
+    }
+
+    let reader = png::Decoder::new(element.reader().ok()?).read_info().ok()?;
+    let info = reader.info();
+
+    info.uncompressed_latin1_text
+        .iter()
+        .find(|e| e.keyword == keyword)
+        .map(|e| e.text.clone())
+        .or_else(|| info.utf8_text
+            .iter()
+            .find(|e| e.keyword == keyword)
+            .and_then(|e| e.get_text().ok())
+        )
+}
+
+/// Get tags from webui-like prompt.
+/// Plain terms are replaced with tags they're aliases of,
+/// extra networks and embeddings get [TagType::Model] type
+fn prompt_tags(prompt: &str) -> impl Iterator<Item = Tag> {
+    prompt_tags_with_embeddings(prompt, &[])
+}
+
+/// Get tags from webui-like prompt, where plain terms named as one of `embeddings` are embeddings
+fn prompt_tags_with_embeddings<'a>(
+    prompt: &str, 
+    embeddings: &'a [String]
+) -> impl Iterator<Item = Tag> + 'a {
+    parse_prompt(prompt)
+        .into_iter()
+        .filter_map(|term| match term.kind {
+            TermKind::Tag if embeddings.contains(&term.text) => {
+                Tag::new(&term.text, None, TagType::Model)
+            },
+            TermKind::Tag => {
+                let name = STORAGE.get().and_then(|s| s.lookup_alias(&term.text))
+                    .unwrap_or(term.text);
+                Tag::new(&name, None, TagType::Tag)
+            },
+            TermKind::Lora
+            | TermKind::Hypernetwork
+            | TermKind::Embedding => Tag::new(&term.text, None, TagType::Model),
+        })
+}
+
+/// Get positive and negative prompts from raw metadata of stable diffusion generator.
+/// NovelAI is not supported, as its prompts are parsed differently
+fn stored_prompts(source: MetadataSource, raw_meta: &str) -> Option<(String, String)> {
+    match source {
+        MetadataSource::Webui => {
+            let mut meta = iter_metadata(raw_meta);
+            let (_, prompt) = meta.next()?;
+            let (_, neg) = meta.next()?;
+            Some((prompt.into_owned(), neg.into_owned()))
+        },
+        MetadataSource::ComfyUI => serde_json::from_str::<metadata::comfyui::Metadata>(raw_meta)
+            .ok()
+            .map(|meta| (meta.prompt, meta.negative_prompt)),
+        MetadataSource::InvokeAI => metadata::invokeai::Metadata::parse(raw_meta)
+            .map(|meta| (meta.prompt, meta.negative_prompt)),
+        MetadataSource::Fooocus => metadata::fooocus::Metadata::parse(raw_meta)
+            .map(|meta| (meta.prompt, meta.negative_prompt)),
+        _ => None
+    }
+}
+
+/// Names of embeddings that are referenced in prompts by bare name
+fn embedding_names(source: MetadataSource, raw_meta: &str) -> Vec<String> {
+    match source {
+        MetadataSource::Webui => metadata::webui::embedding_names(raw_meta),
+        _ => vec![],
+    }
+}
+
+/// Get tags that parser of the source added to element, 
+/// reconstructed from raw metadata (`None` for passthrough elements)
+pub fn parser_tags(source: MetadataSource, raw_meta: Option<&str>) -> Vec<Tag> {
+    let Some(parser) = Parser::from_source(source) else {
+        return vec![]
+    };
+
+    let tags = match (source, raw_meta) {
+        (_, None) => vec![],
+        (MetadataSource::NovelAI, Some(raw_meta)) => serde_json::from_str(raw_meta)
+            .map(|meta| novelai::positive_tags(&meta))
+            .unwrap_or_default(),
+        (_, Some(raw_meta)) => stored_prompts(source, raw_meta)
+            .map(|(prompt, _)| {
+                prompt_tags_with_embeddings(&prompt, &embedding_names(source, raw_meta)).collect()
+            })
+            .unwrap_or_default(),
+    };
+
+    tags
+        .into_iter()
+        .chain(parser.source_tag())
+        .collect()
+}
+
+/// Get tags from negative prompt in raw metadata of stable diffusion generator
+pub fn negative_tags(source: MetadataSource, raw_meta: &str) -> Vec<Tag> {
+    match source {
+        MetadataSource::NovelAI => serde_json::from_str(raw_meta)
+            .map(|meta| novelai::negative_tags(&meta))
+            .unwrap_or_default(),
+        _ => stored_prompts(source, raw_meta)
+            .map(|(_, neg)| {
+                prompt_tags_with_embeddings(&neg, &embedding_names(source, raw_meta)).collect()
+            })
+            .unwrap_or_default(),
+    }
 }
 
 /// Trim pairs of ({[]}) braces expr wrapped into
@@ -1,10 +1,9 @@
-use std::{io::Cursor, borrow::Cow};
 use anyhow::Context;
 use nndb_common::metadata::novelai::Metadata;
 
-use crate::{model::{write::{ElementMetadata, Tag}, TagType}, dao::STORAGE};
+use crate::{model::{write::{ElementMetadata, Tag}, TagType}, dao::STORAGE, CONFIG};
 
-use super::{ElementPrefab, is_png};
+use super::{ElementPrefab, Parser, is_png, embedded::Embedded, stealth};
 
 
 /// Parse NovelAI prompt
@@ -29,65 +28,137 @@ fn parse_prompt(prompt: &str) -> impl Iterator<Item = &str> + '_ {
         })
 }
 
-/// Check if png contains `Software = NovelAI`
-pub fn can_parse(element: &ElementPrefab) -> bool {
-    // PNG header
+/// Make tag from prompt term, replacing alias with the tag it belongs to
+fn alias_tag(term: &str, tag_type: TagType) -> Option<Tag> {
+    let name = STORAGE.get().and_then(|s| s.lookup_alias(term));
+    Tag::new(name.as_deref().unwrap_or(term), None, tag_type)
+}
+
+/// Get tags from prompt, including V4 character captions
+pub fn positive_tags(meta: &Metadata) -> Vec<Tag> {
+    let char_type = match CONFIG.novelai_character_tags {
+        true => TagType::Character,
+        false => TagType::Tag,
+    };
+
+    let char_tags = meta
+        .characters()
+        .flat_map(|(c, _)| parse_prompt(&c.char_caption))
+        .map(|t| (t, char_type));
+
+    parse_prompt(&meta.prompt)
+        .map(|t| (t, TagType::Tag))
+        .chain(char_tags)
+        .filter_map(|(t, typ)| alias_tag(t, typ))
+        .collect()
+}
+
+/// Get tags from undesired content, including V4 negative character captions
+pub fn negative_tags(meta: &Metadata) -> Vec<Tag> {
+    let char_ucs = meta
+        .characters()
+        .filter_map(|(_, uc)| uc)
+        .flat_map(|c| parse_prompt(&c.char_caption));
+
+    parse_prompt(&meta.uc)
+        .chain(char_ucs)
+        .filter_map(|t| alias_tag(t, TagType::Tag))
+        .collect()
+}
+
+/// NovelAI text fields
+struct Fields {
+    /// `Software`, should be `NovelAI`
+    software: Option<String>,
+    /// `Description`
+    prompt: Option<String>,
+    /// `Comment`, JSON with other parameters
+    comment: Option<String>,
+}
+
+/// Read NovelAI fields from stealth metadata JSON, with the same keys as text chunks
+fn read_stealth_fields(element: &ElementPrefab) -> Option<Fields> {
+    let json: serde_json::Value = serde_json::from_str(stealth::read(element)?).ok()?;
+    
+    let string = |key: &str| json.get(key).map(|v| match v {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    });
+
+    Some(Fields {
+        software: string("Software"),
+        prompt: string("Description"),
+        comment: string("Comment"),
+    })
+}
+
+/// Read NovelAI fields from PNG text chunks (or alpha channel if they're stripped or not NovelAI's), 
+/// or from EXIF (`Software`, `ImageDescription`, `UserComment`) of compressed images
+fn read_fields(element: &ElementPrefab) -> Option<Fields> {
     if !is_png(element) {
-        return false
+        let embedded = Embedded::read(element);
+        return Some(Fields {
+            software: embedded.ascii(exif::Tag::Software),
+            prompt: embedded.ascii(exif::Tag::ImageDescription),
+            comment: embedded.user_comment(),
+        })
     }
+
+    let dec = png::Decoder::new(element.reader().ok()?);
+    let reader = dec.read_info().ok()?;
+    let info = reader.info();
+
+    let latin1 = |key: &str| info.uncompressed_latin1_text
+        .iter()
+        .find(|e| e.keyword == key)
+        .map(|e| e.text.clone());
     
-    let mut cursor = Cursor::new(&element.data);
-    let dec = png::Decoder::new(&mut cursor);
-    if let Ok(reader) = dec.read_info() {
-        for entry in &reader.info().uncompressed_latin1_text {
-            if let ("Software", "NovelAI") = 
-                (entry.keyword.as_str(), entry.text.as_str()) {
-                return true
-            } 
+    // Prompt can be in iTXt entry or tEXt entry with key "Description"
+    let prompt = latin1("Description").or_else(|| info.utf8_text
+        .iter()
+        .find(|e| e.keyword == "Description")
+        .and_then(|e| e.get_text().ok())
+    );
+
+    let software = latin1("Software");
+    if software.as_deref() != Some("NovelAI") {
+        // Text chunks may be stripped or rewritten by image editor, 
+        // leaving only stealth metadata
+        if let Some(fields) = read_stealth_fields(element) {
+            return Some(fields)
         }
     }
-    false
+
+    Some(Fields {
+        software,
+        prompt,
+        comment: latin1("Comment"),
+    })
+}
+
+/// Check if element contains `Software = NovelAI`
+pub fn can_parse(element: &ElementPrefab) -> bool {
+    read_fields(element).is_some_and(|f| f.software.as_deref() == Some("NovelAI"))
 }
 
 pub fn extract_metadata(
     element: &ElementPrefab
 ) -> anyhow::Result<ElementMetadata> {
-    let mut cursor = Cursor::new(&element.data);
-    let dec = png::Decoder::new(&mut cursor);
-    let reader = dec.read_info()?;
-    
-    // Prompt can be in iTXt entry or tEXt entry with key "Description"
-    let prompt = reader.info().uncompressed_latin1_text.iter().find_map(|e| {
-        match e.keyword.as_str() {
-            "Description" => Some(Cow::Borrowed(e.text.as_str())),
-            _ => None
-        }
-    }).or_else(|| reader.info().utf8_text.iter().find_map(|e| {
-        match e.keyword.as_str() {
-            "Description" => Some(Cow::Owned(e.get_text().ok()?)),
-            _ => None    
-        }   
-    })).context("prompt not found")?;
-    
-    let others = reader.info().uncompressed_latin1_text.iter().find_map(|e| 
-        match e.keyword.as_str() {
-            "Comment" => Some(&e.text),
-            _ => None
-        }
-    ).context("novelai metadata not found")?;
+    let fields = read_fields(element).context("failed to read metadata")?;
+    let prompt = fields.prompt.context("prompt not found")?;
+    let others = fields.comment.context("novelai metadata not found")?;
         
-    let tags = parse_prompt(&prompt)
-        .filter_map(|t| {
-            let name = STORAGE.get().and_then(|s| s.lookup_alias(t));
-            Tag::new(name.as_deref().unwrap_or(t), None, TagType::Tag)
-        })
-        .chain(Tag::new("novelai_generated", None, TagType::Metadata))
-        .collect();
-    
-    let mut meta: Metadata = serde_json::from_str(others)?;
+    let mut meta: Metadata = serde_json::from_str(&others)?;
     
     // Merge prompt
-    meta.prompt = prompt;
+    meta.prompt = prompt.into();
+
+    let tags = positive_tags(&meta)
+        .into_iter()
+        .chain(Parser::NovelAI.source_tag())
+        .collect();
+
+    let neg_tags = negative_tags(&meta);
 
     let raw_meta = serde_json::to_string(&meta)?;
     
@@ -96,6 +167,7 @@ pub fn extract_metadata(
         src_time: None,
         group: Some(meta.seed),
         raw_meta: Some(raw_meta),
-        tags
+        tags,
+        neg_tags,
     })
 }
@@ -111,7 +111,8 @@ impl Pixiv {
             src_time: Some(illust.create_date),
             raw_meta,
             group: Some(illust.id),
-            tags
+            tags,
+            neg_tags: vec![],
         }
     }
     
@@ -0,0 +1,118 @@
+//! Caption files, that are stored next to the element with the same stem
+//!
+//! `image.txt`/`image.caption` - comma-separated tags (kohya-style datasets)
+//! `image.json` - JSON object with `tags`, `tag_string` or `caption` field
+
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+use crate::model::{write::Tag, TagType};
+
+/// Sidecar extensions, in order of priority
+pub const SIDECAR_EXTS: &[&str] = &["txt", "caption", "json"];
+
+/// Escaped with \ braces
+static ESCAPE_REX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\\(.)").unwrap()
+});
+
+/// Sidecar file, found for element
+#[derive(Debug)]
+pub struct Sidecar {
+    /// Path to sidecar file
+    pub path: PathBuf,
+    /// Sidecar contents
+    pub text: String,
+}
+
+impl Sidecar {
+    /// Find and read sidecar of element at `path`
+    pub fn find(path: &Path) -> anyhow::Result<Option<Self>> {
+        let sidecar = SIDECAR_EXTS
+            .iter()
+            .map(|ext| path.with_extension(ext))
+            .find(|p| p.is_file());
+
+        let Some(path) = sidecar else {
+            return Ok(None)
+        };
+
+        let text = std::fs::read_to_string(&path)?;
+
+        Ok(Some(Self { path, text }))
+    }
+
+    /// Check if there is a file with the same stem next to sidecar,
+    /// e.g. `image.jpg` that shares `image.txt` with already imported `image.png`
+    pub fn is_used(&self) -> bool {
+        let (Some(dir), Some(stem)) = (self.path.parent(), self.path.file_stem()) else {
+            return false
+        };
+
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return false
+        };
+
+        entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.file_stem() == Some(stem) && p.is_file())
+            .any(|p| !p
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| SIDECAR_EXTS.iter().any(|s| s.eq_ignore_ascii_case(e)))
+            )
+    }
+
+    /// Extract tags from sidecar
+    pub fn tags(&self) -> Vec<Tag> {
+        let is_json = self.path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+
+        if !is_json {
+            return parse_caption(&self.text, ',').collect()
+        }
+
+        serde_json::from_str(&self.text)
+            .map(|json| json_tags(&json))
+            .unwrap_or_default()
+    }
+}
+
+/// Extract tags from sidecar contents stored without file extension.
+/// Contents are JSON sidecar if they are JSON object
+pub fn sidecar_tags(text: &str) -> Vec<Tag> {
+    match serde_json::from_str(text) {
+        Ok(json @ Value::Object(_)) => json_tags(&json),
+        _ => parse_caption(text, ',').collect(),
+    }
+}
+
+/// Extract tags from JSON sidecar
+fn json_tags(json: &Value) -> Vec<Tag> {
+    match (&json["tags"], &json["tag_string"], &json["caption"]) {
+        // ["tag", "tag"]
+        (Value::Array(tags), ..) => tags
+            .iter()
+            .filter_map(|t| t.as_str())
+            .filter_map(|t| Tag::new(t.trim(), None, TagType::Tag))
+            .collect(),
+        (Value::String(tags), ..) => parse_caption(tags, ',').collect(),
+        // Booru-like whitespace separated tags
+        (_, Value::String(tags), _) => parse_caption(tags, ' ').collect(),
+        (.., Value::String(caption)) => parse_caption(caption, ',').collect(),
+        _ => vec![]
+    }
+}
+
+/// Split caption with `sep` and unescape tags
+fn parse_caption(caption: &str, sep: char) -> impl Iterator<Item = Tag> + '_ {
+    caption
+        .split(sep)
+        .map(|t| ESCAPE_REX.replace_all(t.trim(), "$1"))
+        .filter_map(|t| Tag::new(&t, None, TagType::Tag))
+}
@@ -0,0 +1,203 @@
+//! Stealth pnginfo: metadata hidden in least significant bits of pixels
+//!
+//! https://github.com/ashen-sensored/sd_webui_stealth_pnginfo
+//!
+//! Bits are read column by column, starting from top left pixel,
+//! either from alpha channel or from red, green and blue channels of each pixel:
+//! signature, 32-bit big-endian payload length in bits, then payload itself.
+//! Payload is gzipped if signature ends with `comp`
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+
+use super::{ElementPrefab, is_png};
+
+/// Channels that hold payload bits
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Alpha,
+    Rgb,
+}
+
+/// Known signatures with mode they're written in and whether payload is gzipped
+const SIGNATURES: &[(&[u8], Mode, bool)] = &[
+    (b"stealth_pnginfo", Mode::Alpha, false),
+    (b"stealth_pngcomp", Mode::Alpha, true),
+    (b"stealth_rgbinfo", Mode::Rgb, false),
+    (b"stealth_rgbcomp", Mode::Rgb, true),
+];
+
+/// Length of every signature
+const SIGNATURE_LEN: usize = 15;
+
+/// Decoded pixels of image
+struct Pixels {
+    buf: Vec<u8>,
+    width: usize,
+    height: usize,
+    line_size: usize,
+    channels: usize,
+}
+
+impl Pixels {
+    /// Iterate over bits hidden with `mode`
+    fn bits(&self, mode: Mode) -> impl Iterator<Item = u8> + '_ {
+        let channels = match mode {
+            Mode::Alpha => self.channels - 1..self.channels,
+            Mode::Rgb => 0..3,
+        };
+
+        (0..self.width)
+            .flat_map(|x| (0..self.height).map(move |y| (x, y)))
+            .flat_map(move |(x, y)| {
+                let pixel = y * self.line_size + x * self.channels;
+                self.buf[pixel..][channels.clone()].iter().map(|c| c & 1)
+            })
+    }
+
+    /// Iterate over bytes hidden with `mode`
+    fn bytes(&self, mode: Mode) -> impl Iterator<Item = u8> + '_ {
+        let mut bits = self.bits(mode);
+        std::iter::from_fn(move || (0..8).try_fold(0u8, |byte, _| Some(byte << 1 | bits.next()?)))
+    }
+}
+
+/// Read payload hidden in pixels of PNG.
+/// Payload is decoded once and kept in element
+pub fn read(element: &ElementPrefab) -> Option<&str> {
+    element.stealth.get_or_init(|| decode(element)).as_deref()
+}
+
+fn decode(element: &ElementPrefab) -> Option<String> {
+    if !is_png(element) {
+        return None
+    }
+
+    let mut decoder = png::Decoder::new(element.reader().ok()?);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+
+    let (channels, modes): (_, &[Mode]) = match reader.output_color_type().0 {
+        png::ColorType::Rgba => (4, &[Mode::Alpha, Mode::Rgb]),
+        png::ColorType::Rgb => (3, &[Mode::Rgb]),
+        png::ColorType::GrayscaleAlpha => (2, &[Mode::Alpha]),
+        _ => return None
+    };
+
+    let info = reader.info();
+    let (width, height) = (info.width as usize, info.height as usize);
+    let interlaced = info.interlaced;
+    let line_size = reader.output_line_size(info.width);
+
+    let mut pixels = Pixels {
+        buf: vec![0; reader.output_buffer_size()],
+        width,
+        height,
+        line_size,
+        channels,
+    };
+
+    // Signature is in the first rows, unless image is shorter than signature,
+    // so other images are rejected without decoding them whole
+    let mut decoded = 0;
+    let mut decode_rows = |pixels: &mut Pixels, until: usize| -> Option<()> {
+        if interlaced {
+            // Rows of interlaced image are not in order
+            if decoded == 0 {
+                reader.next_frame(&mut pixels.buf).ok()?;
+                decoded = height;
+            }
+            return Some(())
+        }
+
+        while decoded < until {
+            let row = reader.next_row().ok()??;
+            let start = decoded * line_size;
+            pixels.buf[start..start + line_size].copy_from_slice(row.data());
+            decoded += 1;
+        }
+        Some(())
+    };
+
+    decode_rows(&mut pixels, height.min(SIGNATURE_LEN * 8))?;
+
+    let (mode, gzipped) = SIGNATURES
+        .iter()
+        .filter(|(_, mode, _)| modes.contains(mode))
+        .find(|(signature, mode, _)| pixels.bytes(*mode).take(SIGNATURE_LEN).eq(signature.iter().copied()))
+        .map(|&(_, mode, gzipped)| (mode, gzipped))?;
+
+    decode_rows(&mut pixels, height)?;
+
+    let mut bytes = pixels.bytes(mode).skip(SIGNATURE_LEN);
+    let len_bytes: Vec<u8> = bytes.by_ref().take(4).collect();
+    let len = u32::from_be_bytes(len_bytes.try_into().ok()?) as usize / 8;
+    // Reject garbage length before allocating
+    let bits_per_pixel = match mode {
+        Mode::Alpha => 1,
+        Mode::Rgb => 3,
+    };
+    if len > width * height * bits_per_pixel / 8 {
+        return None
+    }
+
+    let payload: Vec<u8> = bytes.take(len).collect();
+    if payload.len() < len {
+        return None
+    }
+
+    if gzipped {
+        let mut text = String::new();
+        GzDecoder::new(payload.as_slice()).read_to_string(&mut text).ok()?;
+        Some(text)
+    } else {
+        String::from_utf8(payload).ok()
+    }
+}
+
+#[test]
+fn test_read_stealth() {
+    use std::io::Write;
+    use flate2::{write::GzEncoder, Compression};
+    use super::FileType;
+
+    let text = r#"{"Software": "NovelAI", "Description": "1girl"}"#;
+    let (width, height) = (64, 48);
+
+    // Hide payload in alpha channel of opaque image
+    let encode = |signature: &[u8], payload: &[u8]| {
+        let mut data = signature.to_vec();
+        data.extend((payload.len() as u32 * 8).to_be_bytes());
+        data.extend(payload);
+
+        let mut pixels = vec![255u8; width * height * 4];
+        let bits = data.iter().flat_map(|b| (0..8).rev().map(move |i| b >> i & 1));
+        let positions = (0..width).flat_map(|x| (0..height).map(move |y| (x, y)));
+        for (bit, (x, y)) in bits.zip(positions) {
+            pixels[(y * width + x) * 4 + 3] = 254 | bit;
+        }
+
+        let mut png = vec![];
+        let mut encoder = png::Encoder::new(&mut png, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.write_header().unwrap().write_image_data(&pixels).unwrap();
+        
+        ElementPrefab {
+            path: "test.png".into(),
+            data: Some(png),
+            file_type: FileType::PNG,
+            stealth: Default::default(),
+        }
+    };
+
+    let plain = encode(b"stealth_pnginfo", text.as_bytes());
+    assert_eq!(read(&plain), Some(text));
+
+    let mut gz = GzEncoder::new(vec![], Compression::default());
+    gz.write_all(text.as_bytes()).unwrap();
+    let gzipped = encode(b"stealth_pngcomp", &gz.finish().unwrap());
+    assert_eq!(read(&gzipped), Some(text));
+
+    let none = encode(b"not_a_signature", text.as_bytes());
+    assert_eq!(read(&none), None);
+}
@@ -8,7 +8,7 @@ use tracing_actix_web::TracingLogger;
 use tracing_subscriber::fmt::writer::Tee;
 use util::LateInit;
 
-use crate::dao::{STORAGE, StorageBackend};
+use crate::{dao::{STORAGE, StorageBackend, FutureBlock}, model::ImportStats};
 
 mod model;
 mod dao;
@@ -17,36 +17,53 @@ mod service;
 mod config; 
 mod util;
 mod api;
+mod watcher;
+mod archive;
 
-/// Spawn periodic import tasks
+/// Spawn periodic import tasks.
+/// Each run is saved to import history
 async fn import_spawner() {
     // Different delays are used here to drive tasks out of sync
     // TODO: Random delays or maybe make this thing more pipelined?..
     
     util::task_with_interval(|| async {
-        match service::scan_files().await {
-            Ok(count) => info!(count, "added elements to db"),
+        match service::periodic_run(service::scan_files()).await {
+            Ok(stats) => info!(count=stats.imported, "added elements to db"),
             Err(e) => error!(?e, "failed to scan files"),
         }
     }, Duration::from_secs(300)).await;
 
     util::task_with_interval(|| async {
-        match service::update_metadata().await {
+        let task = async { 
+            let metadata_fetched = service::update_metadata().await?;
+            Ok(ImportStats { metadata_fetched, ..Default::default() })
+        };
+        match service::periodic_run(task).await {
             Ok(_) => info!("updated metadata"),
             Err(e) => error!(?e, "failed to update metadata"),
         }        
     }, Duration::from_secs(310)).await;
 
     util::task_with_interval(|| async {
-        match service::group_elements_by_signature().await {
+        let task = async { 
+            let groups_formed = service::group_elements_by_signature().await?;
+            Ok(ImportStats { groups_formed, ..Default::default() })
+        };
+        match service::periodic_run(task).await {
             Ok(_) => info!("grouped elements"),
             Err(e) => error!(?e, "failed to group elements"),
         }        
     }, Duration::from_secs(320)).await;
 
-    util::blocking_task_with_interval(|| match service::make_thumbnails() {
-        Ok(_) => info!("made thumbnails"),
-        Err(e) => error!(?e, "failed to make thumbnails"),
+    util::blocking_task_with_interval(|| {
+        let task = async { 
+            let thumbnails_made = service::make_thumbnails()?;
+            Ok(ImportStats { thumbnails_made, ..Default::default() })
+        };
+        match service::periodic_run(task).blocking_run() {
+            Ok(_) => info!("made thumbnails"),
+            Err(e) => error!(?e, "failed to make thumbnails"),
+        }
     }, Duration::from_secs(330)).await;
 }
 
@@ -92,6 +109,11 @@ async fn main() -> anyhow::Result<()> {
         import_spawner().await;
     }
 
+    if CONFIG.watch_input_folder {
+        info!(path=CONFIG.input_folder, "Watching input folder");
+        watcher::watch_input_folder()?;
+    }
+
     info!(addr=CONFIG.bind_address, port=CONFIG.port, "Starting server");
     HttpServer::new(|| {
         let mut app = App::new()
@@ -107,6 +129,14 @@ async fn main() -> anyhow::Result<()> {
             .service(api::import_status)
             .service(api::control)
             .service(api::summary)
+            .service(api::upload)
+            .service(api::import_preview)
+            .service(api::element_by_hash)
+            .service(api::import_failures)
+            .service(api::import_failure)
+            .service(api::import_history)
+            .service(api::collections)
+            .service(api::import_models)
         ;
 
         app = if CONFIG.element_pool.serve {
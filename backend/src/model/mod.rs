@@ -1,9 +1,14 @@
-pub use nndb_common::{TagType, Summary, UtcDateTime};
+pub use nndb_common::{
+    TagType, Summary, UtcDateTime, ImportFailure, ImportRun, ImportStats, ImportTrigger,
+    Collection
+};
 
 pub const SIGNATURE_LEN: usize = 544;
 pub const MD5_LEN: usize = 16;
+pub const SHA256_LEN: usize = 32;
 
 pub type Md5Hash = [u8; MD5_LEN];
+pub type Sha256Hash = [u8; SHA256_LEN];
 pub type Signature = [i8; SIGNATURE_LEN];
 
 use crate::dao::SliceShim;
@@ -11,6 +16,7 @@ use crate::dao::SliceShim;
 pub mod read;
 pub mod write;
 pub mod danbooru;
+pub mod registry;
 
 /// Metadata for element group
 #[derive(sqlx::FromRow)]
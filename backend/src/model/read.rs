@@ -22,6 +22,16 @@ pub struct Element {
     pub has_thumb: bool,
     /// Whether element is animation
     pub animated: bool,
+    /// MIME type of element file
+    pub mime: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// File size in bytes
+    pub size: Option<i64>,
+    /// Duration of video in seconds
+    pub duration: Option<f64>,
+    /// Video codec
+    pub codec: Option<String>,
     /// Group of similar images (decided by comparing image signatures)
     pub group_id: Option<u32>,
 }   
@@ -0,0 +1,356 @@
+use std::{collections::HashMap, path::Path, io::{Read, Seek, SeekFrom}, fs::File};
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Sha256, Digest};
+
+use crate::util::AsHex;
+
+/// Extensions of model files, stripped from model names
+pub const MODEL_EXTS: &[&str] = &[".safetensors", ".ckpt", ".pt", ".pth", ".bin", ".gguf"];
+
+/// Offset of data hashed to get AutoV1 hash
+const AUTOV1_OFFSET: u64 = 0x100000;
+/// Length of data hashed to get AutoV1 hash
+const AUTOV1_LEN: u64 = 0x10000;
+
+/// Max size of safetensors header that will be read
+const MAX_HEADER_LEN: u64 = 100 * 1024 * 1024;
+
+/// Kind of model in registry
+#[derive(sqlx::Type, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+#[repr(u8)]
+pub enum ModelType {
+    Checkpoint   = 0,
+    Lora         = 1,
+    Embedding    = 2,
+    Hypernetwork = 3,
+}
+
+impl ModelType {
+    /// Parse type name, civitai names are accepted too
+    pub fn parse(s: &str) -> Option<Self> {
+        let ty = match s.to_lowercase().as_str() {
+            "checkpoint" | "model" | "ckpt" => ModelType::Checkpoint,
+            "lora" | "locon" | "lycoris" | "dora" => ModelType::Lora,
+            "embedding" | "textualinversion" | "ti" => ModelType::Embedding,
+            "hypernetwork" | "hypernet" => ModelType::Hypernetwork,
+            _ => return None
+        };
+        Some(ty)
+    }
+
+    /// Get model type by folders in path relative to models folder,
+    /// webui and ComfyUI layouts are supported.
+    /// Returns `None` for files outside of known folders (VAE, ControlNet, upscalers, etc.)
+    pub fn from_path(rel_path: &Path) -> Option<Self> {
+        rel_path
+            .parent()
+            .into_iter()
+            .flat_map(Path::iter)
+            .find_map(|f| Self::from_folder(&f.to_string_lossy()))
+    }
+
+    /// Get model type by name of folder that holds models of this type
+    fn from_folder(folder: &str) -> Option<Self> {
+        let ty = match folder.to_lowercase().as_str() {
+            "stable-diffusion" | "checkpoints" | "unet" | "diffusion_models" => ModelType::Checkpoint,
+            "lora" | "loras" | "lycoris" => ModelType::Lora,
+            "embeddings" => ModelType::Embedding,
+            "hypernetworks" => ModelType::Hypernetwork,
+            _ => return None
+        };
+        Some(ty)
+    }
+}
+
+/// Variant of model file hash
+#[derive(sqlx::Type, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+#[repr(u8)]
+pub enum HashKind {
+    /// Old webui hash: SHA-256 of 64 KiB at 1 MiB offset, 8 hex digits
+    AutoV1 = 0,
+    /// Webui `Model hash`: first 10 hex digits of SHA-256
+    AutoV2 = 1,
+    /// Webui `Lora hashes` of safetensors: SHA-256 of tensor data
+    /// without header, 12 hex digits
+    AutoV3 = 2,
+    /// Webui `Lora hashes` of other files: first 12 hex digits of SHA-256
+    Short  = 3,
+    /// SHA-256 of whole file
+    Sha256 = 4,
+}
+
+impl HashKind {
+    pub fn parse(s: &str) -> Option<Self> {
+        let kind = match s.to_lowercase().as_str() {
+            "autov1" => HashKind::AutoV1,
+            "autov2" => HashKind::AutoV2,
+            "autov3" => HashKind::AutoV3,
+            "short" => HashKind::Short,
+            "sha256" => HashKind::Sha256,
+            _ => return None
+        };
+        Some(kind)
+    }
+
+    /// Count of hex digits
+    pub fn hex_len(&self) -> usize {
+        match self {
+            HashKind::AutoV1 => 8,
+            HashKind::AutoV2 => 10,
+            HashKind::AutoV3 | HashKind::Short => 12,
+            HashKind::Sha256 => 64,
+        }
+    }
+}
+
+/// Known model to write
+#[derive(Debug)]
+pub struct Model {
+    /// Name without folders and extension
+    pub name: String,
+    pub model_type: ModelType,
+    /// Base model family, e.g. `SDXL 1.0`
+    pub base_model: Option<String>,
+    /// Lowercase hex hashes
+    pub hashes: Vec<(HashKind, String)>,
+}
+
+impl Model {
+    pub fn new(name: &str, model_type: ModelType, base_model: Option<String>) -> Self {
+        Self {
+            name: model_stem(name).to_owned(),
+            model_type,
+            base_model,
+            hashes: vec![],
+        }
+    }
+
+    /// Add hash if it's a valid hex string of expected length.
+    /// Short variants are derived from full SHA-256
+    pub fn add_hash(&mut self, kind: HashKind, hash: &str) {
+        let hash = hash.trim().to_lowercase();
+        if hash.len() != kind.hex_len() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return
+        }
+
+        if kind == HashKind::Sha256 {
+            for short in [HashKind::AutoV2, HashKind::Short] {
+                self.add_hash(short, &hash[..short.hex_len()]);
+            }
+        }
+
+        if !self.hashes.iter().any(|(k, _)| *k == kind) {
+            self.hashes.push((kind, hash));
+        }
+    }
+}
+
+impl Model {
+    /// Hash model file and read base model from safetensors header.
+    ///
+    /// Blocks current thread
+    pub fn from_file(path: &Path, model_type: ModelType) -> anyhow::Result<Self> {
+        let name = path
+            .file_name()
+            .context("no file name")?
+            .to_string_lossy();
+        let mut file = File::open(path)?;
+
+        // Safetensors layout: header length (u64 LE), JSON header, tensor data
+        let (header, data_start) = if path.extension().is_some_and(|e| e == "safetensors") {
+            let mut len = [0; 8];
+            file.read_exact(&mut len)?;
+            let len = u64::from_le_bytes(len);
+            if len > MAX_HEADER_LEN {
+                bail!("invalid safetensors header length: {len}");
+            }
+
+            let mut header = vec![0; len as usize];
+            file.read_exact(&mut header)?;
+            let header: Value = serde_json::from_slice(&header)?;
+            (Some(header), 8 + len)
+        } else {
+            (None, 0)
+        };
+
+        let base_model = header
+            .as_ref()
+            .and_then(|h| h.get("__metadata__"))
+            .and_then(|m| m.get("ss_base_model_version").or_else(|| m.get("modelspec.architecture")))
+            .and_then(Value::as_str)
+            .map(str::to_owned);
+
+        // Hash all variants in one pass
+        file.rewind()?;
+        let mut full = Sha256::new();
+        let mut data = Sha256::new();
+        let mut autov1 = Sha256::new();
+        let mut buf = vec![0; 1024 * 1024];
+        let mut pos = 0;
+
+        loop {
+            let n = match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let chunk = &buf[..n];
+
+            // Part of the chunk that lies in [from, to) of the file
+            let part = |from: u64, to: u64| {
+                let clamp = |offset: u64| offset.saturating_sub(pos).min(n as u64) as usize;
+                &chunk[clamp(from)..clamp(to)]
+            };
+
+            full.update(chunk);
+            data.update(part(data_start, u64::MAX));
+            autov1.update(part(AUTOV1_OFFSET, AUTOV1_OFFSET + AUTOV1_LEN));
+            pos += n as u64;
+        }
+
+        let mut model = Model::new(&name, model_type, base_model);
+        let hex = |hash: &[u8]| AsHex(hash).to_string();
+        model.add_hash(HashKind::Sha256, &hex(&full.finalize()));
+        model.add_hash(HashKind::AutoV1, &hex(&autov1.finalize())[..HashKind::AutoV1.hex_len()]);
+        if header.is_some() {
+            model.add_hash(HashKind::AutoV3, &hex(&data.finalize())[..HashKind::AutoV3.hex_len()]);
+        }
+
+        Ok(model)
+    }
+}
+
+/// Get AutoV1 hash of model file, reading only hashed part of it.
+///
+/// Blocks current thread
+pub fn autov1_hash(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(AUTOV1_OFFSET))?;
+
+    let mut data = vec![];
+    file.take(AUTOV1_LEN).read_to_end(&mut data)?;
+
+    let hash = AsHex(&Sha256::digest(&data)).to_string();
+    Ok(hash[..HashKind::AutoV1.hex_len()].to_owned())
+}
+
+/// Strip folders and known extension from model name,
+/// e.g. `SDXL\sd_xl_base_1.0.safetensors` -> `sd_xl_base_1.0`
+pub fn model_stem(name: &str) -> &str {
+    let name = name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(name)
+        .trim();
+
+    MODEL_EXTS
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(name)
+}
+
+/// Model in JSON list, civitai-like
+#[derive(Deserialize)]
+struct ListEntry {
+    name: String,
+    #[serde(rename = "type")]
+    model_type: Option<String>,
+    #[serde(alias = "baseModel")]
+    base_model: Option<String>,
+    /// Hash kind -> hash
+    #[serde(default)]
+    hashes: HashMap<String, String>,
+}
+
+/// Parse list of models, either JSON array or CSV with header.
+///
+/// JSON: `[{"name": ..., "type": ..., "base_model": ..., "hashes": {"SHA256": ..., "AutoV2": ...}}]`
+///
+/// CSV: `name,type,base_model,sha256,autov2,...` (any order, only `name` is required)
+///
+/// Type defaults to checkpoint, unknown hash kinds are ignored
+pub fn parse_list(text: &str) -> anyhow::Result<Vec<Model>> {
+    let model = |name: &str, ty: Option<&str>, base: Option<&str>| -> anyhow::Result<Model> {
+        let model_type = match ty.filter(|t| !t.is_empty()) {
+            Some(t) => ModelType::parse(t).with_context(|| format!("unknown model type `{t}`"))?,
+            None => ModelType::Checkpoint,
+        };
+        let base = base.filter(|b| !b.is_empty()).map(str::to_owned);
+        Ok(Model::new(name, model_type, base))
+    };
+
+    if text.trim_start().starts_with('[') {
+        let entries: Vec<ListEntry> = serde_json::from_str(text)?;
+
+        return entries
+            .into_iter()
+            .map(|e| {
+                let mut m = model(&e.name, e.model_type.as_deref(), e.base_model.as_deref())?;
+                for (kind, hash) in &e.hashes {
+                    if let Some(kind) = HashKind::parse(kind) {
+                        m.add_hash(kind, hash);
+                    }
+                }
+                Ok(m)
+            })
+            .collect()
+    }
+
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let header = split_csv_line(lines.next().context("empty list")?);
+    let column = |name: &str| header.iter().position(|h| h.eq_ignore_ascii_case(name));
+
+    let Some(name_col) = column("name") else {
+        bail!("`name` column not found")
+    };
+    let type_col = column("type");
+    let base_col = column("base_model");
+    let hash_cols: Vec<_> = header
+        .iter()
+        .enumerate()
+        .filter_map(|(i, h)| Some((i, HashKind::parse(h)?)))
+        .collect();
+
+    lines
+        .map(|line| {
+            let row = split_csv_line(line);
+            let field = |col: Option<usize>| col
+                .and_then(|c| row.get(c))
+                .map(String::as_str);
+
+            let name = field(Some(name_col)).context("row without name")?;
+            let mut m = model(name, field(type_col), field(base_col))?;
+            for (col, kind) in &hash_cols {
+                if let Some(hash) = field(Some(*col)) {
+                    m.add_hash(*kind, hash);
+                }
+            }
+            Ok(m)
+        })
+        .collect()
+}
+
+/// Split CSV line to fields, fields may be quoted with `"`
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // Doubled quote is escaped one
+            '"' if quoted && chars.next_if_eq(&'"').is_some() => field.push('"'),
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_owned()),
+            c => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_owned());
+
+    fields
+}
@@ -1,7 +1,7 @@
 use std::path::PathBuf;
 
 use nndb_common::search::TAG_REX;
-use crate::import::Parser;
+use crate::import::{Parser, Sidecar};
 
 use super::*;
 
@@ -65,8 +65,18 @@ pub struct ElementToParse {
     pub filename: String,
     /// Name that file had before rename
     pub orig_filename: String,
+    /// Path that file had relative to input folder, `/`-separated
+    pub orig_path: String,
+    /// MIME type detected by file content
+    pub mime: &'static str,
+    /// File size in bytes
+    pub size: u64,
+    /// Dimensions, duration, etc.
+    pub media: MediaInfo,
     /// Hash of whole file
     pub hash: Md5Hash,
+    /// Collision-resistant hash of whole file
+    pub sha256: Sha256Hash,
     /// Importer that will be used for file
     pub importer_id: Parser,
     /// Whether element is animation
@@ -75,6 +85,31 @@ pub struct ElementToParse {
     pub signature: Option<Signature>,
     /// True if failed to read image
     pub broken: bool,
+    /// Caption file found next to the element
+    pub sidecar: Option<Sidecar>,
+}
+
+/// Media properties of element file
+#[derive(Debug, Default)]
+pub struct MediaInfo {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Duration of video in seconds
+    pub duration: Option<f64>,
+    /// Video codec name (ffprobe naming)
+    pub codec: Option<String>,
+}
+
+impl ElementToParse {
+    /// Remove caption file of the element, if any.
+    /// Caption is kept if other file in its folder still uses it
+    pub fn remove_sidecar(&self) {
+        if let Some(sidecar) = &self.sidecar {
+            if !sidecar.is_used() {
+                std::fs::remove_file(&sidecar.path).ok();
+            }
+        }
+    }
 }
 
 impl AsRef<ElementToParse> for ElementToParse {
@@ -95,8 +130,21 @@ pub struct ElementMetadata {
     pub group: Option<i64>,
     /// Tags of the element
     pub tags: Vec<Tag>,
+    /// Tags from negative prompt of the generation
+    pub neg_tags: Vec<Tag>,
 }  
 
+/// Outcome of adding element to DB
+#[derive(Debug)]
+pub enum AddStatus {
+    /// Element was added with this id
+    Added(u32),
+    /// Element with the same hash already exists
+    Duplicate(u32),
+    /// Element wasn't added
+    Failed(anyhow::Error),
+}
+
 pub struct Wiki {
     /// Wiki title (primary tag name)
     pub title: String,
@@ -1,20 +1,27 @@
-use std::path::PathBuf;
-use anyhow::{Context, anyhow};
+use std::{path::{PathBuf, Path}, collections::{HashMap, HashSet}};
+use anyhow::Context;
 use atomic::{Atomic, Ordering};
-use futures::{stream::FuturesUnordered, StreamExt};
+use chrono::Utc;
+use futures::{stream::FuturesUnordered, StreamExt, Future};
 use rayon::prelude::*;
 use reqwest::{StatusCode, Client};
+use nndb_common::{ImportPreview, Duplicate, MetadataSource};
 use serde::{Serialize, Deserialize};
-use tokio::sync::mpsc::channel;
+use tokio::sync::{mpsc::channel, Semaphore};
 use tracing::{error, info};
 use walkdir::WalkDir;
 use itertools::Itertools;
 
 use crate::{
     dao::{STORAGE, FutureBlock}, 
-    import::{ElementPrefab, ANIMATION_EXTS, IMAGE_EXTS, FetchStatus},
-    model::write::{ElementWithMetadata, Wiki},
-    CONFIG, util::{self, Procedure}, config::ReadFiles
+    import::{ElementPrefab, FileType, FetchStatus, Parser},
+    model::{
+        write::{self, ElementWithMetadata, Wiki, AddStatus}, 
+        TagType, Md5Hash, ImportStats, ImportTrigger, UtcDateTime,
+        registry::{self, Model, ModelType, MODEL_EXTS, model_stem}
+    },
+    CONFIG, util::{self, Procedure, ProcedureUpdater}, config::ReadFiles,
+    archive
 };
 
 /// Experimentaly decided optimal image signature distance 
@@ -33,140 +40,527 @@ pub static GROUP_ELEMENTS_LOCK: Procedure = Procedure::new();
 pub static MAKE_THUMBNAILS_LOCK: Procedure = Procedure::new();
 /// Indicate state if update_danbooru_wikis()
 pub static FETCH_WIKI_LOCK: Procedure = Procedure::new();
+/// Indicate state of preview_import()
+pub static PREVIEW_IMPORT_LOCK: Procedure = Procedure::new();
+/// Indicate state of scan_models()
+pub static SCAN_MODELS_LOCK: Procedure = Procedure::new();
+/// Indicate state of reparse_metadata()
+pub static REPARSE_METADATA_LOCK: Procedure = Procedure::new();
+
+/// Check if file at `path` can be imported judging by its content.
+/// Archives are considered importable too
+pub fn is_importable(path: &Path) -> bool {
+    archive::is_archive(path) 
+    || FileType::detect(path).is_ok_and(|t| t.is_some())
+}
+
+/// Get path of file relative to input folder. 
+/// Files extracted from archives get path as if archive was unpacked in place
+pub fn input_rel_path(path: &Path) -> &Path {
+    path.strip_prefix(&CONFIG.input_folder)
+        .ok()
+        .or_else(|| archive::staged_rel_path(path))
+        .unwrap_or_else(|| Path::new(path.file_name().unwrap_or(path.as_os_str())))
+}
+
+/// Files to import, with archives extracted.
+/// Extracted files are removed on drop
+struct Expanded {
+    /// Loose and extracted files
+    files: Vec<PathBuf>,
+    /// Successfully extracted archives with their files
+    archives: Vec<(PathBuf, Vec<PathBuf>)>,
+    /// Archives that failed to extract
+    failed: Vec<(PathBuf, anyhow::Error)>,
+    /// Archives that have files which failed to import and weren't quarantined
+    keep: HashSet<PathBuf>,
+    /// Folder with extracted files
+    staging: Option<PathBuf>,
+}
+
+impl Expanded {
+    /// Extract all archives among `files`.
+    ///
+    /// Blocks current thread
+    fn new(files: Vec<PathBuf>) -> anyhow::Result<Self> {
+        let (archives, mut files): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .partition(|p| archive::is_archive(p));
+
+        if archives.is_empty() {
+            return Ok(Self { 
+                files, 
+                archives: vec![], 
+                failed: vec![], 
+                keep: HashSet::new(), 
+                staging: None 
+            })
+        }
+
+        let staging = archive::new_staging()?;
+        let mut extracted = vec![];
+        let mut failed = vec![];
+
+        for path in archives {
+            match archive::extract(&path, &staging) {
+                Ok(inner) => {
+                    info!(archive=%path.display(), count=inner.len(), "extracted archive");
+                    files.extend(inner.iter().cloned());
+                    extracted.push((path, inner));
+                },
+                Err(e) => failed.push((path, e)),
+            }
+        }
+
+        Ok(Self { 
+            files, 
+            archives: extracted, 
+            failed, 
+            keep: HashSet::new(), 
+            staging: Some(staging) 
+        })
+    }
+
+    /// Get archive that file was extracted from
+    fn archive_of(&self, path: &Path) -> Option<&Path> {
+        self.archives
+            .iter()
+            .find(|(_, files)| files.iter().any(|f| f == path))
+            .map(|(archive, _)| archive.as_path())
+    }
+
+    /// Quarantine file that failed to import.
+    /// If extracted file wasn't quarantined, its archive is kept
+    /// and the failure is recorded for the archive
+    async fn fail(&mut self, path: &Path, err: &anyhow::Error) {
+        let archive = self.archive_of(path).map(Path::to_owned);
+        if !quarantine(path, archive.as_deref(), err).await {
+            self.keep.extend(archive);
+        }
+    }
+
+    /// Remove archives which files were all imported or quarantined
+    fn remove_archives(&self) {
+        if CONFIG.testing_mode {
+            return
+        }
+
+        for (path, _) in &self.archives {
+            if self.keep.contains(path) {
+                info!(archive=%path.display(), "archive has failed files, keeping it");
+            } else if let Err(e) = std::fs::remove_file(path) {
+                error!(?e, archive=%path.display(), "failed to remove archive");
+            }
+        }
+    }
+}
+
+impl Drop for Expanded {
+    /// Remove extracted files
+    fn drop(&mut self) {
+        if let Some(staging) = &self.staging {
+            if let Err(e) = std::fs::remove_dir_all(staging) {
+                error!(?e, "failed to remove staging folder");
+            }
+        }
+    }
+}
+
+/// Recursively collect all importable files in `root` 
+pub fn find_files(root: &Path) -> Vec<PathBuf> {
+    WalkDir::new(root)
+        .into_iter()
+        .filter_map(|e| {
+            let e = match e {
+                Ok(e) => e,
+                Err(e) => {
+                    error!(?e, "failed to get entry");
+                    return None;
+                },
+            };
+
+            let path = e.path();   
+
+            if !path.is_file() || !is_importable(path) {
+                return None;
+            }
+
+            Some(path.to_owned())
+        })
+        .collect()
+}
 
 /// Scan `CONFIG.input_folder` directory for new files and import them.
 /// Will do nothing if already running
-pub async fn scan_files() -> anyhow::Result<u32> {
+pub async fn scan_files() -> anyhow::Result<ImportStats> {
     let _guard = match SCAN_FILES_LOCK.begin() {
         Some(guard) => guard,
-        None => return Ok(0) 
+        None => return Ok(ImportStats::default()) 
     };
-    
-    let (tx, mut rx) = channel(1000);
 
-    let updater = _guard.updater();
+    let files = tokio::task::spawn_blocking(|| {
+        find_files(Path::new(&CONFIG.input_folder))
+    }).await?;
     
-    // CPU-heavy task: read and hash files
-    tokio::task::spawn_blocking(move || {
-        let files: Vec<_> = WalkDir::new(&CONFIG.input_folder)
-            .into_iter()
-            .filter_map(|e| {
-                let e = match e {
-                    Ok(e) => e,
-                    Err(e) => {
-                        error!(?e, "failed to get entry");
-                        return None;
-                    },
-                };
+    add_files(files, _guard.updater()).await
+}
 
-                let path = e.path();   
+/// Import only listed files.
+/// Returns `None` if file scan is already running
+pub async fn import_files(files: Vec<PathBuf>) -> anyhow::Result<Option<ImportStats>> {
+    let _guard = match SCAN_FILES_LOCK.begin() {
+        Some(guard) => guard,
+        None => return Ok(None) 
+    };
 
-                if !path.is_file() {
-                    return None;
-                }
+    add_files(files, _guard.updater()).await.map(Some)
+}
 
-                let ext = path
-                    .extension()
-                    .and_then(|e| e.to_str())
-                    .map(|e| e.to_lowercase());
-            
-                match ext.as_deref() {
-                    Some(e) if IMAGE_EXTS.contains(&e) => Some((path.to_owned(), false)),
-                    Some(e) if ANIMATION_EXTS.contains(&e) => Some((path.to_owned(), true)),
-                    _ => None,
-                }
-            })
-            .collect();
+/// Count outcomes of adding elements
+pub fn count_statuses(statuses: &[AddStatus]) -> ImportStats {
+    let mut stats = ImportStats { 
+        files_seen: statuses.len() as u32, 
+        ..Default::default() 
+    };
 
-        updater.set_action_count(files.len() as u32);
+    for status in statuses {
+        match status {
+            AddStatus::Added(_) => stats.imported += 1,
+            AddStatus::Duplicate(_) => stats.duplicates += 1,
+            AddStatus::Failed(_) => stats.failed += 1,
+        }
+    }
 
-        // Closure for hashing file
-        let process_file = |path: PathBuf, data| -> anyhow::Result<ElementWithMetadata> {
-            let prefab = ElementPrefab {
-                path: path.clone(),
-                data: data?
-            };
-            let element = util::hash_file(prefab)
-                .context(path.display().to_string())?;
+    stats
+}
 
-            Ok(element)
-        };
+/// Read and hash files, passing each result to `on_hashed`.
+/// Files are read according to `CONFIG.read_files`,
+/// and no more than `CONFIG.max_files_in_memory` are processed at the same time.
+///
+/// Blocks current thread
+fn hash_files<F>(files: Vec<PathBuf>, updater: &ProcedureUpdater, on_hashed: F) 
+where F: Fn(PathBuf, anyhow::Result<ElementWithMetadata>) + Send + Sync {
+    updater.set_action_count(files.len() as u32);
+
+    // Limits count of files that are read or being hashed at the same time
+    let limit = Semaphore::new(CONFIG.max_files_in_memory.max(1) as usize);
+
+    // Closure for reading file, that will block if limit is reached.
+    // Returned permit should be held until file data is freed
+    let read_file = |path: PathBuf| {
+        // Semaphore is never closed
+        let permit = limit.acquire().blocking_run().unwrap();
+        let res = ElementPrefab::load(path.clone());
+        (path, res, permit)
+    };
 
-        // Report and wrap into Option
-        let process_file = |path, data| {
-            // Report that file was processed
-            updater.increment();
-            match process_file(path, data) {
-                Ok(r) => Some(r),
-                Err(e) => {
-                    error!(?e, "failed to hash element");
-                    None
-                },
-            }
-        };
+    // Closure for hashing file
+    let process_file = |path: &Path, prefab| -> anyhow::Result<ElementWithMetadata> {
+        let element = util::hash_file(prefab?)
+            .context(path.display().to_string())?;
 
-        // Choose multithreaded or singlethreaded read
-        match CONFIG.read_files {
-            // When doing scan this way, there are a meaningful number of files in memory
-            // (Usually equal to count of threads in pool)
-            ReadFiles::Parallel => {
-                files.into_par_iter()
-                    .filter_map(|(path, _)| {
-                        let data = std::fs::read(&path);
-                        process_file(path, data)
-                    })
-                    .for_each(|meta| {
-                        // Send data, cause we cant access tokio context on this thread
-                        tx.blocking_send(meta).ok();
-                    });
-            },
-            // This way file data will be pulled from storage lazily according to parallel demand  
-            ReadFiles::Sequential => {
-                files.into_iter()
-                    // Read each file in this thread
-                    .map(|(path, _)| {
-                        let res = std::fs::read(&path);
-                        (path, res)
-                    })
-                    // Offload hashing to multiple threads
-                    .par_bridge()
-                    .filter_map(|(path, res)| process_file(path, res))
-                    .for_each(|meta| {
-                        tx.blocking_send(meta).ok();
-                    });  
-            },
-        };
+        Ok(element)
+    };
+
+    // Report and pass result
+    let process_file = |(path, prefab, _permit): (PathBuf, _, _)| {
+        // Report that file was processed
+        updater.increment();
+        let res = process_file(&path, prefab);
+        on_hashed(path, res);
+    };
+
+    // Choose multithreaded or singlethreaded read
+    match CONFIG.read_files {
+        // When doing scan this way, there are a meaningful number of files in memory
+        // (Usually equal to count of threads in pool)
+        ReadFiles::Parallel => {
+            files.into_par_iter()
+                .map(read_file)
+                .for_each(process_file);
+        },
+        // This way file data will be pulled from storage lazily according to parallel demand  
+        ReadFiles::Sequential => {
+            files.into_iter()
+                // Read each file in this thread
+                .map(read_file)
+                // Offload hashing to multiple threads
+                .par_bridge()
+                .for_each(process_file);  
+        },
+    };
+}
 
+/// Read, hash and add files to DB.
+/// Returns file import statistics
+async fn add_files(
+    files: Vec<PathBuf>, 
+    updater: ProcedureUpdater<'static>
+) -> anyhow::Result<ImportStats> {
+    let mut expanded = tokio::task::spawn_blocking(|| Expanded::new(files)).await??;
+    for (path, e) in &expanded.failed {
+        quarantine(path, None, e).await;
+    }
+    let files = std::mem::take(&mut expanded.files);
+
+    let mut stats = ImportStats {
+        files_seen: (files.len() + expanded.failed.len()) as u32,
+        failed: expanded.failed.len() as u32,
+        ..Default::default()
+    };
+
+    let (tx, mut rx) = channel(1000);
+    
+    // CPU-heavy task: read and hash files
+    tokio::task::spawn_blocking(move || {
+        // Send data, cause we cant access tokio context on this thread
+        hash_files(files, &updater, |path, res| { tx.blocking_send((path, res)).ok(); });
 
         // tx should be dropped here
     });
 
-    let mut count = 0;
+    /// Add elements and quarantine ones that failed
+    async fn add(
+        elements: &[ElementWithMetadata], 
+        stats: &mut ImportStats,
+        expanded: &mut Expanded
+    ) -> anyhow::Result<()> {
+        let statuses = STORAGE.add_elements(elements).await?;
+        
+        for (ElementWithMetadata(e, ..), status) in elements.iter().zip(&statuses) {
+            if let AddStatus::Failed(err) = status {
+                expanded.fail(&e.path, err).await;
+            }
+        }
+
+        let added = count_statuses(&statuses);
+        stats.imported += added.imported;
+        stats.duplicates += added.duplicates;
+        stats.failed += added.failed;
+
+        Ok(())
+    }
     
     // Add elements in chunks of 1000
     let mut buffer = Vec::with_capacity(1000);
-    while let Some(meta) = rx.recv().await {
-        buffer.push(meta);
+    while let Some((path, res)) = rx.recv().await {
+        match res {
+            Ok(meta) => buffer.push(meta),
+            Err(e) => {
+                stats.failed += 1;
+                expanded.fail(&path, &e).await
+            },
+        }
 
         if buffer.len() == 1000 {
-            count += STORAGE.add_elements(&buffer).await?;
+            add(&buffer, &mut stats, &mut expanded).await?;
             buffer.clear();
         }
     }
 
     // Add remaining
-    count += STORAGE.add_elements(&buffer).await?;
+    add(&buffer, &mut stats, &mut expanded).await?;
 
-    Ok(count)
+    // Extracted files are removed on drop, even if import was interrupted.
+    // Archives are removed only if there's no other copy of their failed files
+    expanded.remove_archives();
+
+    Ok(stats)
+}
+
+/// Move file that failed to import to `CONFIG.quarantine_folder` (if set) 
+/// and record the failure, so it can be inspected later.
+/// If file extracted from `archive` is not quarantined, failure is recorded for the archive.
+/// Returns `true` if file was quarantined
+async fn quarantine(path: &Path, archive: Option<&Path>, err: &anyhow::Error) -> bool {
+    error!(?err, path=%path.display(), "failed to import file");
+
+    // Keep path relative to input folder, as it may contain tags
+    let orig_path = input_rel_path(path);
+
+    let quarantined = match &CONFIG.quarantine_folder {
+        Some(folder) => {
+            let dst = folder.join(orig_path);
+            let res = dst
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| if CONFIG.testing_mode {
+                    std::fs::copy(path, &dst).map(|_| ())
+                } else {
+                    util::move_file(path, &dst)
+                });
+
+            match res {
+                Ok(_) => Some(dst),
+                Err(e) => {
+                    error!(?e, path=%path.display(), "failed to quarantine file");
+                    None
+                }
+            }
+        },
+        None => None,
+    };
+
+    // Extracted file will be removed with staging folder, so point to its archive instead
+    let (new_path, orig_path, error) = match (&quarantined, archive) {
+        (Some(dst), _) => (dst.as_path(), orig_path, format!("{err:#}")),
+        (None, Some(archive)) => (
+            archive, 
+            input_rel_path(archive), 
+            format!("{}: {err:#}", orig_path.display())
+        ),
+        (None, None) => (path, orig_path, format!("{err:#}")),
+    };
+    
+    if let Err(e) = STORAGE.add_import_failure(
+        &new_path.to_string_lossy(),
+        &orig_path.to_string_lossy(),
+        &error
+    ).await {
+        error!(?e, "failed to record import failure");
+    }
+
+    quarantined.is_some()
 }
 
+/// Move file that failed to import back to input folder and try to import it again
+pub async fn retry_import_failure(id: u32) -> anyhow::Result<()> {
+    let failure = STORAGE
+        .get_import_failure(id)
+        .await?
+        .context("import failure not found")?;
+
+    let path = PathBuf::from(&failure.path);
+    let dst = Path::new(&CONFIG.input_folder).join(&failure.orig_path);
+
+    if path != dst {
+        if let Some(parent) = dst.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        util::move_file(&path, &dst)?;
+    }
+
+    STORAGE.remove_import_failure(id).await?;
+
+    let start = Utc::now();
+    match import_files(vec![dst]).await? {
+        Some(stats) => finish_import(ImportTrigger::Retry, start, stats).await,
+        // File will be picked up by the next scan
+        None => {
+            info!("file scan is running, retry postponed");
+            Ok(())
+        },
+    }
+}
+
+/// Delete file that failed to import and forget about it
+pub async fn discard_import_failure(id: u32) -> anyhow::Result<()> {
+    let failure = STORAGE
+        .get_import_failure(id)
+        .await?
+        .context("import failure not found")?;
+
+    match std::fs::remove_file(&failure.path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => (),
+    }
+
+    STORAGE.remove_import_failure(id).await?;
+    
+    Ok(())
+}
+
+
+/// Analyze files in `CONFIG.input_folder` without importing them.
+/// Returns `None` if already running
+pub async fn preview_import() -> anyhow::Result<Option<Vec<ImportPreview>>> {
+    let _guard = match PREVIEW_IMPORT_LOCK.begin() {
+        Some(guard) => guard,
+        None => return Ok(None) 
+    };
+
+    let updater = _guard.updater();
+
+    let (mut results, mut expanded) = tokio::task::spawn_blocking(move || {
+        let files = find_files(Path::new(&CONFIG.input_folder));
+        let mut expanded = Expanded::new(files)?;
+        let results = parking_lot::Mutex::new(vec![]);
+        hash_files(
+            std::mem::take(&mut expanded.files), 
+            &updater, 
+            |path, res| results.lock().push((path, res))
+        );
+        anyhow::Ok((results.into_inner(), expanded))
+    }).await??;
+
+    let failed = std::mem::take(&mut expanded.failed);
+    drop(expanded);
+    results.extend(failed.into_iter().map(|(path, e)| (path, Err(e))));
+
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    
+    // Hashes of already analyzed files
+    let mut seen: HashMap<Md5Hash, String> = HashMap::new();
+    let mut previews = Vec::with_capacity(results.len());
+
+    for (path, res) in results {
+        let rel_path = input_rel_path(&path)
+            .display()
+            .to_string();
+
+        let preview = match res {
+            Ok(ElementWithMetadata(e, meta, parser)) => {
+                let duplicate = match STORAGE.get_element_id_by_hash(&e.hash).await? {
+                    Some(id) => Some(Duplicate::Element(id)),
+                    None => seen.get(&e.hash).cloned().map(Duplicate::File),
+                };
+                seen.entry(e.hash).or_insert_with(|| rel_path.clone());
+
+                let path_tags = util::get_tags_from_path(&path);
+                let sidecar_tags = e.sidecar
+                    .as_ref()
+                    .map(|s| s.tags())
+                    .unwrap_or_default();
+                let tags = meta.tags
+                    .iter()
+                    .chain(&path_tags)
+                    .chain(&sidecar_tags)
+                    .map(|t| t.name().to_owned())
+                    .collect();
+
+                ImportPreview {
+                    path: rel_path,
+                    source: Some(parser.metadata_source()),
+                    tags,
+                    broken: e.broken,
+                    duplicate,
+                    error: None,
+                }
+            },
+            Err(e) => ImportPreview {
+                path: rel_path,
+                source: None,
+                tags: vec![],
+                broken: false,
+                duplicate: None,
+                error: Some(format!("{e:#}")),
+            }
+        };
+
+        previews.push(preview);
+    }
+
+    Ok(Some(previews))
+}
 
 /// Fetch metadata for all pending imports.
-/// Will do nothing if already running
-pub async fn update_metadata() -> anyhow::Result<()> {
+/// Will do nothing if already running.
+/// Returns count of elements that got metadata
+pub async fn update_metadata() -> anyhow::Result<u32> {
     let _guard = match UPDATE_METADATA_LOCK.begin() {
         Some(guard) => guard,
-        None => return Ok(())
+        None => return Ok(0)
     };
     
     let imports = STORAGE
@@ -175,6 +569,7 @@ pub async fn update_metadata() -> anyhow::Result<()> {
 
     let updater = _guard.updater();
     updater.set_action_count(imports.len() as u32);
+    let fetched = Atomic::new(0u32);
 
     let mut groups: FuturesUnordered<_> = imports.iter()
         .group_by(|imp| imp.importer_id)
@@ -200,7 +595,9 @@ pub async fn update_metadata() -> anyhow::Result<()> {
                         }
                     };
                     match STORAGE.add_metadata(imp.id, imp.importer_id, &status).await {
-                            Ok(_) => (),
+                            Ok(_) => if let FetchStatus::Success(_) = status {
+                                fetched.fetch_add(1, Ordering::Relaxed);
+                            },
                             Err(e) => error!(?e, ?imp, "failed to add metadata"),
                     }
                     updater.increment();
@@ -211,16 +608,18 @@ pub async fn update_metadata() -> anyhow::Result<()> {
 
     // Wait for all importers to finish
     while groups.next().await.is_some() {}
+    drop(groups);
     
-    Ok(())
+    Ok(fetched.into_inner())
 }
 
 /// Group elements by their image signature.
-/// Will do nothing if already running
-pub async fn group_elements_by_signature() -> anyhow::Result<()> {
+/// Will do nothing if already running.
+/// Returns count of groups that got new elements
+pub async fn group_elements_by_signature() -> anyhow::Result<u32> {
     let _guard = match GROUP_ELEMENTS_LOCK.begin() {
         Some(guard) => guard,
-        None => return Ok(())
+        None => return Ok(0)
     };
 
     /// Group registry
@@ -305,15 +704,16 @@ pub async fn group_elements_by_signature() -> anyhow::Result<()> {
         STORAGE.add_to_group(elem_ids, Some(*group_id)).await?;
     }
     
-    Ok(())
+    Ok(groups.0.len() as u32)
 }
 
 /// Make thumbnails for all files that don't have one.
-/// Will do nothing if already running
-pub fn make_thumbnails() -> anyhow::Result<()> {
+/// Will do nothing if already running.
+/// Returns count of made thumbnails
+pub fn make_thumbnails() -> anyhow::Result<u32> {
     let _guard = match MAKE_THUMBNAILS_LOCK.begin() {
         Some(guard) => guard,
-        None => return Ok(())
+        None => return Ok(0)
     };
 
     let updater = _guard.updater();
@@ -332,39 +732,35 @@ pub fn make_thumbnails() -> anyhow::Result<()> {
     
     let elems: Vec<_> = no_thumbnail
         .into_par_iter()
-        .map_with(
-            (PathBuf::from(&CONFIG.element_pool.path), PathBuf::from(&CONFIG.thumbnails_folder.path)),
-            |(pool, thumb), e| {
-                pool.push(&e.filename);
-                thumb.push(&e.filename);
-                thumb.set_extension("jpeg");
-            
-                let err = if e.animated {
-                    util::make_thumbnail_anim(pool, thumb, THUMBNAIL_SIZE)
+        .filter_map(|e| {
+            let pool = util::element_path(&e.filename);
+            let thumb = util::thumbnail_path(&e.filename);
+        
+            let err = thumb
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .map_err(Into::into)
+                .and_then(|_| if e.animated {
+                    util::make_thumbnail_anim(&pool, &thumb, THUMBNAIL_SIZE)
                 } else {
-                    util::make_thumbnail_image(pool, thumb, THUMBNAIL_SIZE)
-                };
-                
-                pool.pop();
-                thumb.pop();
+                    util::make_thumbnail_image(&pool, &thumb, THUMBNAIL_SIZE)
+                });
 
-                updater.increment();
-                
-                match err {
-                    Ok(_) => Some(e.id),
-                    Err(err) => {
-                        error!(?err, e=e.filename, "failed to make thumbnail");
-                        None
-                    }
+            updater.increment();
+            
+            match err {
+                Ok(_) => Some(e.id),
+                Err(err) => {
+                    error!(?err, e=e.filename, "failed to make thumbnail");
+                    None
                 }
             }
-        )
-        .filter_map(|opt| opt)
+        })
         .collect();
 
     STORAGE.add_thumbnails(&elems).blocking_run()?;
 
-    Ok(())
+    Ok(elems.len() as u32)
 }
 
 /// Remove thumbnail mark from elements that don't actually have thumbnail
@@ -379,23 +775,8 @@ pub fn fix_thumbnails() -> anyhow::Result<()> {
         STORAGE.search_elements("", 0, None, 0).blocking_run()?.0
     };
 
-    let thumbs = std::fs::read_dir(&CONFIG.thumbnails_folder.path)?
-        .flat_map(|e| -> anyhow::Result<String> {
-            let entry = e?;
-            let path = entry.path();
-            let filename = path
-                .file_stem()
-                .ok_or(anyhow!("expected file stem"))?
-                .to_string_lossy();
-            Ok(filename.into_owned())
-        })
-    .collect_vec();
-
     // Retain only elements that have thumbnail
-    elems.retain(|e| {
-        let filename = e.filename.split('.').next().unwrap();
-        thumbs.iter().any(|t| t.starts_with(filename))
-    });
+    elems.retain(|e| util::thumbnail_path(&e.filename).is_file());
     
     let ids = elems.into_iter()
         .map(|e| e.id)
@@ -407,20 +788,136 @@ pub fn fix_thumbnails() -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Manually start import task in strict sequence
-pub async fn manual_import() -> anyhow::Result<()> {
-    scan_files().await?;
-    info!("Scanned files");
-    update_metadata().await?;
+/// Start import tasks in strict sequence: scan input folder and process imported elements.
+/// Run is saved to import history
+pub async fn import_pass(trigger: ImportTrigger) -> anyhow::Result<ImportStats> {
+    let start = Utc::now();
+    
+    let scan = scan_files().await;
+    let mut stats = match &scan {
+        Ok(stats) => {
+            info!(count=stats.imported, "Scanned files");
+            *stats
+        },
+        Err(_) => ImportStats::default(),
+    };
+
+    // Elements imported before may still need processing, even if scan failed
+    let processed = process_imported(&mut stats).await;
+    let res = scan.map(|_| ()).and(processed);
+
+    record_run(trigger, start, &stats, &res).await;
+    res.map(|_| stats)
+}
+
+/// Run one of periodic import tasks and save it to import history
+pub async fn periodic_run<F>(task: F) -> anyhow::Result<ImportStats>
+where F: Future<Output = anyhow::Result<ImportStats>> {
+    let start = Utc::now();
+
+    let (stats, res) = match task.await {
+        Ok(stats) => (stats, Ok(())),
+        Err(e) => (ImportStats::default(), Err(e)),
+    };
+
+    record_run(ImportTrigger::Periodic, start, &stats, &res).await;
+    res.map(|_| stats)
+}
+
+/// Process elements that were imported by other means and save run to import history
+pub async fn finish_import(
+    trigger: ImportTrigger, 
+    start: UtcDateTime, 
+    mut stats: ImportStats
+) -> anyhow::Result<()> {
+    let res = process_imported(&mut stats).await;
+    record_run(trigger, start, &stats, &res).await;
+    res
+}
+
+/// Save import run to history, unless it did nothing
+async fn record_run(
+    trigger: ImportTrigger, 
+    start: UtcDateTime, 
+    stats: &ImportStats, 
+    res: &anyhow::Result<()>
+) {
+    if *stats == ImportStats::default() && res.is_ok() {
+        return
+    }
+
+    let error = res.as_ref().err().map(|e| format!("{e:#}"));
+    if let Err(e) = STORAGE
+        .add_import_run(trigger, start, Utc::now(), stats, error.as_deref())
+        .await {
+        error!(?e, "failed to save import run");
+    }
+}
+
+/// Run tasks that should follow file import: 
+/// fetch metadata, group elements and make thumbnails
+pub async fn process_imported(stats: &mut ImportStats) -> anyhow::Result<()> {
+    stats.metadata_fetched = update_metadata().await?;
     info!("Updated metadata");
-    group_elements_by_signature().await?;
+    stats.groups_formed = group_elements_by_signature().await?;
     info!("Grouped images");
-    tokio::task::spawn_blocking(make_thumbnails).await??;
+    stats.thumbnails_made = tokio::task::spawn_blocking(make_thumbnails).await??;
     info!("Made thumbnails");
 
     Ok(())
 }
 
+/// Import uploaded files, adding `tags` and `src_link` to each of them.
+/// Returns statuses in the same order as `files`
+pub async fn import_uploaded(
+    files: Vec<PathBuf>,
+    tags: Vec<String>,
+    src_link: Option<String>,
+) -> anyhow::Result<Vec<AddStatus>> {
+    // Hash files, remembering which of them have failed
+    let hashed = tokio::task::spawn_blocking(move || {
+        files.into_par_iter()
+            .map(|path| util::hash_file(ElementPrefab::load(path)?))
+            .collect::<Vec<_>>()
+    }).await?;
+
+    // Same files may be imported by scan at the same time
+    let _guard = SCAN_FILES_LOCK.wait().await;
+
+    let mut elements = vec![];
+    let mut statuses: Vec<Option<AddStatus>> = vec![];
+    
+    for res in hashed {
+        match res {
+            Ok(mut elem) => {
+                let meta = &mut elem.1;
+                meta.tags.extend(
+                    tags.iter().filter_map(|t| write::Tag::new(t, None, TagType::Tag))
+                );
+                if src_link.is_some() {
+                    meta.src_link = src_link.clone();
+                }
+                elements.push(elem);
+                statuses.push(None);
+            },
+            Err(e) => {
+                error!(?e, "failed to hash uploaded element");
+                statuses.push(Some(AddStatus::Failed(e)));
+            }
+        }
+    }
+
+    let mut added = STORAGE.add_elements(&elements).await?.into_iter();
+
+    // Fill gaps with statuses of hashed elements
+    let statuses = statuses
+        .into_iter()
+        .map(|s| s.or_else(|| added.next()).unwrap())
+        .collect();
+
+    Ok(statuses)
+}
+
 /// Fetch danbooru wikis to get tags categories and translations.
 /// This may take quite a bit of time.
 pub async fn update_danbooru_wikis() -> anyhow::Result<()> {
@@ -544,4 +1041,155 @@ pub async fn update_danbooru_wikis() -> anyhow::Result<()> {
     STORAGE.reload_tag_aliases_index().await?;
     
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Scan models folder, add new models to model registry
+/// and tag elements that were generated with them.
+/// 
+/// Blocks current thread
+pub fn scan_models() -> anyhow::Result<()> {
+    let _guard = match SCAN_MODELS_LOCK.begin() {
+        Some(guard) => guard,
+        None => return Ok(())
+    };
+
+    let updater = _guard.updater();
+
+    let folder = CONFIG.models_folder
+        .as_deref()
+        .context("models folder is not set")?;
+    let hashed = STORAGE.get_hashed_models().blocking_run()?;
+
+    let files = WalkDir::new(folder)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .filter(|p| {
+            let name = p.to_string_lossy();
+            MODEL_EXTS.iter().any(|ext| name.ends_with(ext))
+        })
+        // Files outside of known folders are not models that generators write
+        .filter_map(|p| {
+            let model_type = ModelType::from_path(p.strip_prefix(folder).unwrap_or(&p))?;
+            Some((p, model_type))
+        })
+        .collect_vec();
+
+    // Hashing takes a while, so models are hashed again only if file was replaced
+    let files = files
+        .into_par_iter()
+        .filter(|(p, ty)| {
+            let key = (model_stem(&p.to_string_lossy()).to_owned(), *ty);
+            match (hashed.get(&key), registry::autov1_hash(p)) {
+                (Some(stored), Ok(hash)) => *stored != hash,
+                _ => true,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    updater.set_action_count(files.len() as u32);
+
+    let models: Vec<_> = files
+        .into_par_iter()
+        .filter_map(|(path, model_type)| {
+            let res = Model::from_file(&path, model_type);
+            updater.increment();
+
+            match res {
+                Ok(model) => Some(model),
+                Err(e) => {
+                    error!(?e, ?path, "failed to read model file");
+                    None
+                }
+            }
+        })
+        .collect();
+
+    info!(count=models.len(), "Scanned models");
+
+    STORAGE.add_models(&models).blocking_run()?;
+    tag_models().blocking_run()
+}
+
+/// Add models from JSON or CSV list to model registry
+/// and tag elements that were generated with them
+pub async fn import_model_list(text: &str) -> anyhow::Result<usize> {
+    let models = registry::parse_list(text)?;
+    STORAGE.add_models(&models).await?;
+    tag_models().await?;
+
+    Ok(models.len())
+}
+
+/// Add tags of registry models to elements and update tag counts
+async fn tag_models() -> anyhow::Result<()> {
+    let count = STORAGE.tag_models().await?;
+    // Tags that were already present got incremented too
+    STORAGE.update_tag_count().await?;
+    info!(count, "Tagged elements with registry models");
+
+    Ok(())
+}
+
+/// Parse metadata of stored elements again with current parsers, 
+/// replacing parsed metadata and tags added by parsers.
+/// Elements can be limited by search `query` and by `source` of their metadata.
+/// Will do nothing if already running.
+/// Returns count of re-parsed elements
+pub fn reparse_metadata(query: Option<&str>, source: Option<MetadataSource>) -> anyhow::Result<u32> {
+    let _guard = match REPARSE_METADATA_LOCK.begin() {
+        Some(guard) => guard,
+        None => return Ok(0)
+    };
+
+    let updater = _guard.updater();
+
+    let targets = STORAGE.get_reparse_targets(query, source).blocking_run()?;
+
+    updater.set_action_count(targets.len() as u32);
+
+    // Parse and write in chunks of 1000, so metadata of whole archive isn't kept in memory
+    // and elements written before failure stay consistently re-parsed
+    let mut count = 0;
+    let res = targets.chunks(1000).try_for_each(|chunk| {
+        let parsed: Vec<_> = chunk
+            .par_iter()
+            .filter_map(|(id, filename)| {
+                let res = ElementPrefab::load(util::element_path(filename))
+                    .map_err(anyhow::Error::from)
+                    .and_then(|prefab| {
+                        let parser = Parser::scan(&prefab);
+                        let meta = parser.extract_metadata(&prefab)?;
+                        Ok((parser.metadata_source(), meta))
+                    });
+
+                match res {
+                    Ok((parsed_source, meta)) => Some((*id, parsed_source, meta)),
+                    Err(e) => {
+                        error!(?e, filename, "failed to re-parse element metadata");
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        for (id, source, meta) in &parsed {
+            STORAGE.replace_parsed_metadata(*id, *source, meta).blocking_run()?;
+        }
+
+        count += parsed.len() as u32;
+        for _ in chunk {
+            updater.increment();
+        }
+
+        Ok::<_, anyhow::Error>(())
+    });
+
+    // Counts of replaced tags are not tracked
+    STORAGE.update_tag_count().blocking_run()?;
+    res?;
+    info!(count, "Re-parsed element metadata");
+
+    Ok(count)
+}
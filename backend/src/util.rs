@@ -1,19 +1,21 @@
-use std::{path::Path, io::SeekFrom, sync::atomic::Ordering, time::{Duration, UNIX_EPOCH}, fmt::Display, process::Command};
+use std::{path::{Path, PathBuf}, io::{SeekFrom, Read}, sync::atomic::Ordering, time::{Duration, UNIX_EPOCH}, fmt::Display, process::Command};
 use anyhow::{Context, bail};
 use atomic::Atomic;
 use futures::Future;
 use md5::{Md5, Digest};
+use sha2::Sha256;
 use nndb_common::{TaskStatus, UtcDateTime};
 use once_cell::sync::OnceCell;
 use tokio::io::{AsyncReadExt, AsyncSeekExt};
-use tracing::error;
+use tracing::{error, warn};
 use itertools::Itertools;
 use crate::{
     model::{Signature, 
-        write::{self, ElementToParse, ElementWithMetadata}, 
-        SIGNATURE_LEN, MD5_LEN
+        write::{self, ElementToParse, ElementWithMetadata, MediaInfo}, 
+        SIGNATURE_LEN, Md5Hash, Sha256Hash
     },
-    import::{TAG_TRIGGER, ElementPrefab, Parser, ANIMATION_EXTS},
+    import::{TAG_TRIGGER, ElementPrefab, Parser, Sidecar},
+    service,
     CONFIG
 };
 
@@ -46,6 +48,16 @@ impl Procedure {
         }
     }
 
+    /// Wait until procedure is not running and begin it
+    pub async fn wait(&self) -> ProcedureGuard<'_> {
+        loop {
+            if let Some(guard) = self.begin() {
+                return guard
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+
     /// Get procedure state
     pub fn state(&self) -> TaskStatus {
         match self.running.load(Ordering::Relaxed) {
@@ -142,6 +154,50 @@ pub fn get_tags_from_path(path: &Path) -> Vec<write::Tag> {
         .collect()
 } 
 
+/// Convert relative path to `/`-separated string, same on all platforms
+pub fn slash_path(path: &Path) -> String {
+    path.iter()
+        .map(|p| p.to_string_lossy())
+        .join("/")
+}
+
+/// Get all directories of `/`-separated relative file path, from outermost.
+///
+/// `a/b/c.png` -> `a`, `a/b`
+pub fn path_dirs(path: &str) -> impl Iterator<Item = &str> {
+    path.match_indices('/').map(|(idx, _)| &path[..idx])
+}
+
+/// Path to element file in element pool
+pub fn element_path(filename: &str) -> PathBuf {
+    CONFIG.element_pool.path.join(CONFIG.pool_layout.rel_path(filename))
+}
+
+/// Move file, copying it if `dst` is on another file system
+pub fn move_file(src: &Path, dst: &Path) -> std::io::Result<()> {
+    match std::fs::rename(src, dst) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            if let Err(e) = std::fs::copy(src, dst) {
+                std::fs::remove_file(dst).ok();
+                return Err(e)
+            }
+            std::fs::remove_file(src)
+        },
+        res => res,
+    }
+}
+
+/// Name of element thumbnail file
+pub fn thumbnail_name(filename: &str) -> String {
+    let stem = filename.split('.').next().unwrap_or(filename);
+    format!("{stem}.jpeg")
+}
+
+/// Path to element thumbnail in thumbnails folder
+pub fn thumbnail_path(filename: &str) -> PathBuf {
+    CONFIG.thumbnails_folder.path.join(CONFIG.pool_layout.rel_path(&thumbnail_name(filename)))
+}
+
 /// Get last file modification date as [chrono::DateTime]
 pub fn get_file_datetime(path: &Path) -> anyhow::Result<UtcDateTime> {
     let dur = path.metadata()?
@@ -152,52 +208,119 @@ pub fn get_file_datetime(path: &Path) -> anyhow::Result<UtcDateTime> {
         .context("failed to construct datetime")    
 }
 
+/// Stream data through MD5 and SHA-256 hashers at once
+pub fn hash_reader(mut reader: impl Read) -> std::io::Result<(Md5Hash, Sha256Hash)> {
+    let mut md5 = Md5::new();
+    let mut sha256 = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        md5.update(&buf[..n]);
+        sha256.update(&buf[..n]);
+    }
+
+    Ok((md5.finalize().into(), sha256.finalize().into()))
+}
+
+/// Parse hex string to fixed size byte array
+pub fn parse_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    // `from_str_radix` would accept `+` sign
+    if hex.len() != N * 2 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None
+    }
+
+    let mut out = [0; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+
+    Some(out)
+}
+
 /// Derive file hash, signature, and, if possible, metadata
 pub fn hash_file(prefab: ElementPrefab) -> anyhow::Result<ElementWithMetadata> {
     let parser_id = Parser::scan(&prefab);
 
-    let hash: [u8; MD5_LEN] = Md5::digest(&prefab.data).into();    
+    let (hash, sha256) = hash_reader(prefab.reader()?)?;
 
     let filename = prefab.path.file_name()
         .context("Expected filename")?
         .to_str()
         .context("Failed to convert filename")?;
     
-    let ext = filename
-        .rsplit('.')
-        .next()
-        .context("Expected extension")?;
-
-    let new_name = format!("{}.{ext}", AsHex(&hash));
+    // Extension is derived from content, as original one may be wrong
+    let file_type = prefab.file_type;
+    let new_name = format!("{}.{}", AsHex(&hash), file_type.ext);
     
-    let animated = ANIMATION_EXTS.contains(&ext);   
-    let (signature, broken) = match animated {
-        false => 'blk: {
+    let size = match &prefab.data {
+        Some(data) => data.len() as u64,
+        None => std::fs::metadata(&prefab.path)?.len(),
+    };
+
+    let animated = file_type.animated;   
+    let (signature, broken, media) = match file_type.image_format() {
+        Some(format) => 'blk: {
             let mut sign = [0; SIGNATURE_LEN];
-            let img = image::load_from_memory(&prefab.data);
+            let img = match &prefab.data {
+                Some(data) => image::load_from_memory_with_format(data, format),
+                None => image::open(&prefab.path),
+            };
             
-            if let Err(e) = img {
-                error!(?e, filename, "failed to load image");
-                break 'blk (None, true)
-            }
+            let img = match img {
+                Ok(img) => img,
+                Err(e) => {
+                    error!(?e, filename, "failed to load image");
+                    break 'blk (None, true, MediaInfo::default())
+                }
+            };
+
+            let media = MediaInfo {
+                width: Some(img.width()),
+                height: Some(img.height()),
+                ..Default::default()
+            };
             
-            let v = image_match::get_image_signature(img.unwrap());           
+            let v = image_match::get_image_signature(img);           
             sign.clone_from_slice(&v);
-            (Some(sign), false)
+            (Some(sign), false, media)
+        },
+        None => {
+            let media = probe_media(&prefab.path).unwrap_or_else(|e| {
+                warn!(?e, filename, "failed to probe video");
+                MediaInfo::default()
+            });
+            (None, false, media)
         },
-        true => (None, false),
     };
 
     let metadata = parser_id.extract_metadata(&prefab)?;
+
+    // Tags from caption file are added on import, like tags from path
+    let sidecar = Sidecar::find(&prefab.path).unwrap_or_else(|e| {
+        error!(?e, filename, "failed to read sidecar");
+        None
+    });
      
     let element = ElementToParse {
         filename: new_name,
         orig_filename: filename.to_owned(),
+        orig_path: slash_path(service::input_rel_path(&prefab.path)),
+        mime: file_type.mime,
+        size,
+        media,
         hash,
+        sha256,
         importer_id: parser_id,
         animated,
         signature,
         broken,
+        sidecar,
         path: prefab.path,
     };
     
@@ -211,7 +334,8 @@ pub fn make_thumbnail_image(
     thumb_out: &Path, 
     (max_width, max_height): (u32, u32) 
 ) -> anyhow::Result<()> {
-    let img = image::open(src)?;    
+    // Guess format by content, elements imported before type detection may have wrong extension
+    let img = image::io::Reader::open(src)?.with_guessed_format()?.decode()?;    
 
     let ratio = img.width() as f32 / img.height() as f32;
 
@@ -269,6 +393,51 @@ pub fn make_thumbnail_anim(
     Ok(())
 }
 
+/// Path to ffprobe, that is expected to be next to configured ffmpeg
+pub fn ffprobe_path() -> Option<PathBuf> {
+    let ffmpeg = Path::new(CONFIG.ffmpeg_path.as_ref()?);
+    let name = ffmpeg.file_name()?.to_str()?.replace("ffmpeg", "ffprobe");
+    Some(ffmpeg.with_file_name(name))
+}
+
+/// Get dimensions, duration and codec of video `src`.
+/// FFProbe required
+pub fn probe_media(src: &Path) -> anyhow::Result<MediaInfo> {
+    let Some(ffprobe) = ffprobe_path() else {
+        bail!("ffmpeg path is not configured");
+    };
+
+    let output = Command::new(ffprobe)
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=width,height,codec_name:format=duration",
+            "-of",
+            "json",
+        ])
+        .arg(src)
+        .output()?;
+
+    if !output.status.success() {
+        bail!("ffprobe exited with {}", output.status);
+    }
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let stream = &json["streams"][0];
+    let dimension = |key| stream[key].as_u64().map(|v| v as u32);
+
+    Ok(MediaInfo {
+        width: dimension("width"),
+        height: dimension("height"),
+        // Duration is a string in ffprobe output
+        duration: json["format"]["duration"].as_str().and_then(|d| d.parse().ok()),
+        codec: stream["codec_name"].as_str().map(str::to_owned),
+    })
+}
+
 /// Read log tail to buf.
 /// Note that due to log could become bigger during read or even be smaller than `bytes`, 
 /// read bytes count won't always correspond to requested `bytes`.
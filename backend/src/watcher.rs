@@ -0,0 +1,144 @@
+//! Event-driven import of files that appear in `CONFIG.input_folder`
+
+use std::{path::{PathBuf, Path}, collections::HashMap, time::{Duration, Instant}};
+
+use chrono::Utc;
+use notify::{Watcher, RecursiveMode, Event, EventKind, event::{AccessKind, AccessMode}};
+use tokio::sync::mpsc::unbounded_channel;
+use tracing::{error, info};
+
+use crate::{service, CONFIG, model::ImportTrigger};
+
+/// File is considered fully written if it had no events and its size
+/// didn't change for this long
+const SETTLE_DELAY: Duration = Duration::from_secs(3);
+
+/// How often pending files are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// File that was created or modified recently
+struct PendingFile {
+    /// Time of the last event or size change
+    last_change: Instant,
+    /// File size at the last check
+    size: Option<u64>,
+}
+
+/// Whether event may indicate new or (re)written file
+fn is_relevant(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_)
+        | EventKind::Modify(_)
+        | EventKind::Access(AccessKind::Close(AccessMode::Write))
+    )
+}
+
+/// Take files that weren't touched for `SETTLE_DELAY`.
+/// Directories are expanded to importable files inside them
+fn take_settled(pending: &mut HashMap<PathBuf, PendingFile>) -> Vec<PathBuf> {
+    let mut settled = vec![];
+
+    pending.retain(|path, file| {
+        if file.last_change.elapsed() < SETTLE_DELAY {
+            return true
+        }
+
+        // File was removed or moved away
+        let Ok(meta) = path.metadata() else {
+            return false
+        };
+
+        if meta.is_dir() {
+            settled.extend(service::find_files(path));
+            return false
+        }
+
+        // Still being written
+        if file.size != Some(meta.len()) {
+            file.size = Some(meta.len());
+            file.last_change = Instant::now();
+            return true
+        }
+
+        if service::is_importable(path) {
+            settled.push(path.clone());
+        }
+        false
+    });
+
+    settled
+}
+
+/// Start watching `CONFIG.input_folder`.
+/// New files will be imported as soon as they are fully written
+pub fn watch_input_folder() -> anyhow::Result<()> {
+    let (tx, mut rx) = unbounded_channel();
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        match res {
+            Ok(event) if is_relevant(&event.kind) => for path in event.paths {
+                tx.send(path).ok();
+            },
+            Ok(_) => (),
+            Err(e) => error!(?e, "input folder watcher error"),
+        }
+    })?;
+
+    watcher.watch(Path::new(&CONFIG.input_folder), RecursiveMode::Recursive)?;
+
+    tokio::spawn(async move {
+        // Watcher stops on drop
+        let _watcher = watcher;
+
+        let mut pending: HashMap<PathBuf, PendingFile> = HashMap::new();
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        // Files that are ready, but weren't imported yet
+        let mut ready = vec![];
+
+        loop {
+            tokio::select! {
+                Some(path) = rx.recv() => {
+                    let file = pending.entry(path).or_insert(PendingFile {
+                        last_change: Instant::now(),
+                        size: None
+                    });
+                    file.last_change = Instant::now();
+                },
+                _ = interval.tick() => {
+                    ready.extend(take_settled(&mut pending));
+                    // Files could be imported by periodic scan meanwhile
+                    ready.retain(|p| p.exists());
+                    ready.sort();
+                    ready.dedup();
+                    if ready.is_empty() {
+                        continue;
+                    }
+
+                    let start = Utc::now();
+                    match service::import_files(ready.clone()).await {
+                        Ok(Some(stats)) => {
+                            info!(count=stats.imported, "added watched elements to db");
+                            ready.clear();
+                            if let Err(e) = service::finish_import(
+                                ImportTrigger::Watcher, 
+                                start, 
+                                stats
+                            ).await {
+                                error!(?e, "failed to process imported elements");
+                            }
+                        },
+                        // Scan is running, retry on next tick
+                        Ok(None) => (),
+                        Err(e) => {
+                            error!(?e, "failed to import watched files");
+                            ready.clear();
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use super::{ParsedMeta, GenerationParams, webui_sampler};
+
+/// Parameters extracted from ComfyUI node graph
+#[derive(Serialize, Deserialize, Default)]
+pub struct Metadata {
+    /// Texts of positive prompt encoders, joined with `, `
+    pub prompt: String,
+    /// Texts of negative prompt encoders, joined with `, `
+    pub negative_prompt: String,
+    pub seed: Option<i64>,
+    pub steps: Option<u32>,
+    pub cfg: Option<f64>,
+    pub sampler: Option<String>,
+    pub scheduler: Option<String>,
+    #[serde(default)]
+    pub denoise: Option<f64>,
+    /// Size of empty latent image
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// Checkpoint (or UNet) file name
+    pub checkpoint: Option<String>,
+    #[serde(default)]
+    pub loras: Vec<Lora>,
+    /// Original `prompt` node graph
+    pub graph: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Lora {
+    pub name: String,
+    pub strength: Option<f64>,
+}
+
+fn kv(key: &str, val: impl ToString, wide: bool) -> (String, String, bool) {
+    (key.to_string(), val.to_string(), wide)
+}
+
+pub fn parse_metadata(raw_meta: &str) -> ParsedMeta {
+    // This meta is coming from server and should be already validated
+    // on extracting stage
+    let meta: Metadata = serde_json::from_str(raw_meta)
+        .expect("failed to parse metadata");
+
+    let mut parsed = vec![
+        kv("Prompt", meta.prompt, true),
+        kv("Negative prompt", meta.negative_prompt, true),
+    ];
+
+    let optional = [
+        ("Steps", meta.steps.map(|v| v.to_string())),
+        ("CFG Scale", meta.cfg.map(|v| v.to_string())),
+        ("Sampler", meta.sampler),
+        ("Scheduler", meta.scheduler),
+        ("Denoise", meta.denoise.map(|v| v.to_string())),
+        ("Seed", meta.seed.map(|v| v.to_string())),
+        ("Model", meta.checkpoint),
+    ];
+
+    for (key, val) in optional {
+        if let Some(val) = val {
+            parsed.push(kv(key, val, false));
+        }
+    }
+
+    if !meta.loras.is_empty() {
+        let loras = meta.loras
+            .iter()
+            .map(|l| match l.strength {
+                Some(s) => format!("{}:{s}", l.name),
+                None => l.name.clone(),
+            })
+            .join(", ");
+        parsed.push(kv("LoRAs", loras, true));
+    }
+
+    parsed
+}
+
+pub fn pretty_raw_meta(raw_meta: &str) -> String {
+    let meta: Metadata = serde_json::from_str(raw_meta)
+        .expect("failed to parse metadata");
+
+    serde_json::to_string_pretty(&meta).unwrap()
+}
+
+pub fn generation_params(raw_meta: &str) -> Option<GenerationParams> {
+    let meta: Metadata = serde_json::from_str(raw_meta).ok()?;
+
+    Some(GenerationParams {
+        steps: meta.steps,
+        sampler: meta.sampler.map(|s| webui_sampler(&s, meta.scheduler.as_deref())),
+        cfg: meta.cfg,
+        seed: meta.seed,
+        width: meta.width,
+        height: meta.height,
+        model: meta.checkpoint,
+        model_hash: None,
+        denoise: meta.denoise,
+        loras: meta.loras.into_iter().map(|l| (l.name, None)).collect(),
+    })
+}
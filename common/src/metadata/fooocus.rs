@@ -0,0 +1,165 @@
+use itertools::Itertools;
+use serde_json::{Value, Map};
+
+use super::{ParsedMeta, GenerationParams, webui_sampler};
+
+/// Fooocus metadata (`fooocus` scheme)
+#[derive(Default)]
+pub struct Metadata {
+    pub prompt: String,
+    pub negative_prompt: String,
+    pub styles: Option<String>,
+    pub performance: Option<String>,
+    pub resolution: Option<String>,
+    pub steps: Option<u64>,
+    pub guidance_scale: Option<f64>,
+    pub sampler: Option<String>,
+    pub scheduler: Option<String>,
+    pub seed: Option<i64>,
+    pub base_model: Option<String>,
+    pub base_model_hash: Option<String>,
+    pub refiner_model: Option<String>,
+    /// (name, weight)
+    pub loras: Vec<(String, Option<f64>)>,
+    pub version: Option<String>,
+}
+
+/// Get value as string, numbers are stringified.
+/// Fooocus writes `None` for missing values
+fn string(json: &Map<String, Value>, key: &str) -> Option<String> {
+    match json.get(key)? {
+        Value::String(s) if s == "None" || s.is_empty() => None,
+        Value::String(s) => Some(s.clone()),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+impl Metadata {
+    /// Check if metadata JSON was written by Fooocus
+    pub fn is_fooocus(json: &Map<String, Value>) -> bool {
+        json.get("metadata_scheme").and_then(Value::as_str) == Some("fooocus")
+            || json.get("version")
+                .and_then(Value::as_str)
+                .is_some_and(|v| v.starts_with("Fooocus"))
+    }
+
+    pub fn parse(raw_meta: &str) -> Option<Self> {
+        let json: Map<String, Value> = serde_json::from_str(raw_meta).ok()?;
+        if !Self::is_fooocus(&json) {
+            return None
+        }
+
+        // LoRAs are written as `lora_combined_<n>: <name> : <weight>`
+        let loras = json
+            .iter()
+            .filter(|(k, _)| k.starts_with("lora_combined_"))
+            .sorted_by_key(|(k, _)| (k.len(), k.as_str()))
+            .filter_map(|(_, v)| v.as_str())
+            .map(|v| match v.rsplit_once(" : ") {
+                Some((name, weight)) => (name.to_owned(), weight.parse().ok()),
+                None => (v.to_owned(), None),
+            })
+            .collect();
+
+        Some(Self {
+            prompt: string(&json, "prompt").unwrap_or_default(),
+            negative_prompt: string(&json, "negative_prompt").unwrap_or_default(),
+            styles: string(&json, "styles"),
+            performance: string(&json, "performance"),
+            resolution: string(&json, "resolution"),
+            steps: string(&json, "steps").and_then(|s| s.parse().ok()),
+            guidance_scale: string(&json, "guidance_scale").and_then(|s| s.parse().ok()),
+            sampler: string(&json, "sampler"),
+            scheduler: string(&json, "scheduler"),
+            // Seed is written as string
+            seed: string(&json, "seed").and_then(|s| s.parse().ok()),
+            base_model: string(&json, "base_model"),
+            base_model_hash: string(&json, "base_model_hash"),
+            refiner_model: string(&json, "refiner_model"),
+            loras,
+            version: string(&json, "version"),
+        })
+    }
+}
+
+fn kv(key: &str, val: impl ToString, wide: bool) -> (String, String, bool) {
+    (key.to_string(), val.to_string(), wide)
+}
+
+pub fn parse_metadata(raw_meta: &str) -> ParsedMeta {
+    let Some(meta) = Metadata::parse(raw_meta) else {
+        return vec![]
+    };
+
+    let mut parsed = vec![
+        kv("Prompt", meta.prompt, true),
+        kv("Negative prompt", meta.negative_prompt, true),
+    ];
+
+    if let Some(styles) = meta.styles {
+        parsed.push(kv("Styles", styles, true));
+    }
+
+    let optional = [
+        ("Performance", meta.performance),
+        ("Resolution", meta.resolution),
+        ("Steps", meta.steps.map(|v| v.to_string())),
+        ("Guidance Scale", meta.guidance_scale.map(|v| v.to_string())),
+        ("Sampler", meta.sampler),
+        ("Scheduler", meta.scheduler),
+        ("Seed", meta.seed.map(|v| v.to_string())),
+        ("Model", meta.base_model),
+        ("Refiner", meta.refiner_model),
+        ("Version", meta.version),
+    ];
+
+    for (key, val) in optional {
+        if let Some(val) = val {
+            parsed.push(kv(key, val, false));
+        }
+    }
+
+    if !meta.loras.is_empty() {
+        let loras = meta.loras
+            .iter()
+            .map(|(name, weight)| match weight {
+                Some(w) => format!("{name}:{w}"),
+                None => name.clone(),
+            })
+            .join(", ");
+        parsed.push(kv("LoRAs", loras, true));
+    }
+
+    parsed
+}
+
+pub fn pretty_raw_meta(raw_meta: &str) -> String {
+    let meta: Value = serde_json::from_str(raw_meta).unwrap();
+
+    serde_json::to_string_pretty(&meta).unwrap()
+}
+
+pub fn generation_params(raw_meta: &str) -> Option<GenerationParams> {
+    let meta = Metadata::parse(raw_meta)?;
+
+    // Resolution is written as `(width, height)`
+    let (width, height) = meta.resolution
+        .as_deref()
+        .and_then(|r| r.trim_matches(['(', ')']).split_once(','))
+        .map(|(w, h)| (w.trim().parse().ok(), h.trim().parse().ok()))
+        .unwrap_or_default();
+
+    Some(GenerationParams {
+        steps: meta.steps.map(|v| v as u32),
+        sampler: meta.sampler.map(|s| webui_sampler(&s, meta.scheduler.as_deref())),
+        cfg: meta.guidance_scale,
+        seed: meta.seed,
+        width,
+        height,
+        model: meta.base_model,
+        model_hash: meta.base_model_hash,
+        denoise: None,
+        loras: meta.loras.into_iter().map(|(name, _)| (name, None)).collect(),
+    })
+}
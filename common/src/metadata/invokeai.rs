@@ -0,0 +1,193 @@
+use itertools::Itertools;
+use serde_json::Value;
+
+use super::{ParsedMeta, GenerationParams, webui_sampler};
+
+/// Parameters common for InvokeAI metadata versions
+#[derive(Default)]
+pub struct Metadata {
+    pub prompt: String,
+    pub negative_prompt: String,
+    pub seed: Option<i64>,
+    pub steps: Option<u64>,
+    pub cfg_scale: Option<f64>,
+    pub scheduler: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Denoising strength of img2img
+    pub strength: Option<f64>,
+    pub model: Option<String>,
+    pub model_hash: Option<String>,
+    /// (name, weight)
+    pub loras: Vec<(String, Option<f64>)>,
+    pub version: Option<String>,
+}
+
+fn string(value: &Value, path: &str) -> Option<String> {
+    value.pointer(path)?.as_str().map(str::to_owned)
+}
+
+fn number(value: &Value, path: &str) -> Option<u32> {
+    value.pointer(path)?.as_u64().map(|v| v as u32)
+}
+
+/// Split v2 prompt to positive and negative parts.
+/// Negative prompt is written in square brackets: `cat [blurry, lowres]`
+fn split_v2_prompt(prompt: &str) -> (String, String) {
+    let mut pos = String::new();
+    let mut neg = String::new();
+    let mut depth = 0;
+
+    for c in prompt.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth > 0 => depth -= 1,
+            c if depth > 0 => neg.push(c),
+            c => pos.push(c),
+        }
+    }
+
+    (pos.trim().to_owned(), neg.trim().to_owned())
+}
+
+impl Metadata {
+    /// Parse `invokeai_metadata` (v3 and later) or `sd-metadata` (v2) JSON
+    pub fn parse(raw_meta: &str) -> Option<Self> {
+        let json: Value = serde_json::from_str(raw_meta).ok()?;
+
+        match json.get("image") {
+            Some(image) => Self::parse_v2(&json, image),
+            None => Self::parse_v3(&json),
+        }
+    }
+
+    fn parse_v2(json: &Value, image: &Value) -> Option<Self> {
+        // Prompt is either a string or a list of weighted prompts
+        let prompt = match image.get("prompt")? {
+            Value::String(s) => s.clone(),
+            Value::Array(prompts) => prompts
+                .iter()
+                .filter_map(|p| p.get("prompt")?.as_str())
+                .join(", "),
+            _ => return None,
+        };
+        let (prompt, negative_prompt) = split_v2_prompt(&prompt);
+
+        Some(Self {
+            prompt,
+            negative_prompt,
+            seed: image.get("seed").and_then(Value::as_i64),
+            steps: image.get("steps").and_then(Value::as_u64),
+            cfg_scale: image.get("cfg_scale").and_then(Value::as_f64),
+            scheduler: string(image, "/sampler"),
+            width: number(image, "/width"),
+            height: number(image, "/height"),
+            strength: image.get("strength").and_then(Value::as_f64),
+            model: string(json, "/model_weights"),
+            model_hash: string(json, "/model_hash"),
+            loras: vec![],
+            version: string(json, "/app_version"),
+        })
+    }
+
+    fn parse_v3(json: &Value) -> Option<Self> {
+        let loras = json
+            .get("loras")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|l| {
+                // v4 renamed `lora` to `model` and `model_name` to `name`
+                let model = l.get("lora").or_else(|| l.get("model"))?;
+                let name = string(model, "/model_name").or_else(|| string(model, "/name"))?;
+                Some((name, l.get("weight").and_then(Value::as_f64)))
+            })
+            .collect();
+
+        Some(Self {
+            prompt: string(json, "/positive_prompt")?,
+            negative_prompt: string(json, "/negative_prompt").unwrap_or_default(),
+            seed: json.get("seed").and_then(Value::as_i64),
+            steps: json.get("steps").and_then(Value::as_u64),
+            cfg_scale: json.get("cfg_scale").and_then(Value::as_f64),
+            scheduler: string(json, "/scheduler"),
+            width: number(json, "/width"),
+            height: number(json, "/height"),
+            strength: json.get("strength").and_then(Value::as_f64),
+            model: string(json, "/model/model_name").or_else(|| string(json, "/model/name")),
+            model_hash: string(json, "/model/hash"),
+            loras,
+            version: string(json, "/app_version"),
+        })
+    }
+}
+
+fn kv(key: &str, val: impl ToString, wide: bool) -> (String, String, bool) {
+    (key.to_string(), val.to_string(), wide)
+}
+
+pub fn parse_metadata(raw_meta: &str) -> ParsedMeta {
+    let Some(meta) = Metadata::parse(raw_meta) else {
+        return vec![]
+    };
+
+    let mut parsed = vec![
+        kv("Prompt", meta.prompt, true),
+        kv("Negative prompt", meta.negative_prompt, true),
+    ];
+
+    let optional = [
+        ("Steps", meta.steps.map(|v| v.to_string())),
+        ("CFG Scale", meta.cfg_scale.map(|v| v.to_string())),
+        ("Scheduler", meta.scheduler),
+        ("Seed", meta.seed.map(|v| v.to_string())),
+        ("Model", meta.model),
+        ("Version", meta.version),
+    ];
+
+    for (key, val) in optional {
+        if let Some(val) = val {
+            parsed.push(kv(key, val, false));
+        }
+    }
+
+    if !meta.loras.is_empty() {
+        let loras = meta.loras
+            .iter()
+            .map(|(name, weight)| match weight {
+                Some(w) => format!("{name}:{w}"),
+                None => name.clone(),
+            })
+            .join(", ");
+        parsed.push(kv("LoRAs", loras, true));
+    }
+
+    parsed
+}
+
+pub fn pretty_raw_meta(raw_meta: &str) -> String {
+    let meta: Value = serde_json::from_str(raw_meta).unwrap();
+
+    serde_json::to_string_pretty(&meta).unwrap()
+}
+
+pub fn generation_params(raw_meta: &str) -> Option<GenerationParams> {
+    let meta = Metadata::parse(raw_meta)?;
+
+    Some(GenerationParams {
+        steps: meta.steps.map(|v| v as u32),
+        // Karras variants have `_k` suffix
+        sampler: meta.scheduler.map(|s| match s.strip_suffix("_k") {
+            Some(s) => webui_sampler(s, Some("karras")),
+            None => webui_sampler(&s, None),
+        }),
+        cfg: meta.cfg_scale,
+        seed: meta.seed,
+        width: meta.width,
+        height: meta.height,
+        model: meta.model,
+        model_hash: meta.model_hash,
+        denoise: meta.strength,
+        loras: meta.loras.into_iter().map(|(name, _)| (name, None)).collect(),
+    })
+}
@@ -5,7 +5,11 @@ use serde::{Deserialize, Serialize};
 
 pub mod novelai;
 pub mod webui;
+pub mod prompt;
 pub mod pixiv;
+pub mod comfyui;
+pub mod invokeai;
+pub mod fooocus;
 
 /// Source of grouping data and/or metadata
 #[cfg_attr(feature = "backend", derive(sqlx::Type))]
@@ -29,6 +33,14 @@ pub enum MetadataSource {
     NovelAI     = 1,
     /// Stable diffusion seed
     Webui       = 2,
+    /// Caption file next to the element
+    Sidecar     = 3,
+    /// Stable diffusion seed
+    ComfyUI     = 4,
+    /// Stable diffusion seed
+    InvokeAI    = 5,
+    /// Stable diffusion seed
+    Fooocus     = 6,
     /// Image signature (id doesn't recorded to db)
     Signature   = 100,
     /// Pixiv illust id
@@ -38,13 +50,110 @@ pub enum MetadataSource {
 /// (key, value, should_be_wide)
 pub type ParsedMeta = Vec<(String, String, bool)>;
 
+/// Generation parameters, normalized across stable diffusion sources
+#[derive(Debug, Default, PartialEq)]
+pub struct GenerationParams {
+    pub steps: Option<u32>,
+    /// Sampler name as written by webui, with scheduler appended, e.g. `DPM++ 2M Karras`.
+    /// See [webui_sampler]
+    pub sampler: Option<String>,
+    pub cfg: Option<f64>,
+    pub seed: Option<i64>,
+    /// Generation width (may differ from element's one after upscale)
+    pub width: Option<u32>,
+    /// Generation height
+    pub height: Option<u32>,
+    /// Checkpoint name
+    pub model: Option<String>,
+    /// Checkpoint hash
+    pub model_hash: Option<String>,
+    /// Denoising strength of img2img
+    pub denoise: Option<f64>,
+    /// LoRA names with optional hashes, used to resolve them in model registry.
+    /// Not stored with other parameters
+    pub loras: Vec<(String, Option<String>)>,
+}
+
+/// Webui names of sampler ids used by ComfyUI, Fooocus, InvokeAI and NovelAI
+const SAMPLER_NAMES: &[(&str, &str)] = &[
+    ("euler", "Euler"),
+    ("euler_a", "Euler a"),
+    ("euler_ancestral", "Euler a"),
+    ("heun", "Heun"),
+    ("lms", "LMS"),
+    ("dpm_2", "DPM2"),
+    ("kdpm_2", "DPM2"),
+    ("dpm_2_ancestral", "DPM2 a"),
+    ("kdpm_2_a", "DPM2 a"),
+    ("dpm_fast", "DPM fast"),
+    ("dpm_adaptive", "DPM adaptive"),
+    ("dpmpp_2s_ancestral", "DPM++ 2S a"),
+    ("dpmpp_sde", "DPM++ SDE"),
+    ("dpmpp_sde_gpu", "DPM++ SDE"),
+    ("dpmpp_2m", "DPM++ 2M"),
+    ("dpmpp_2m_sde", "DPM++ 2M SDE"),
+    ("dpmpp_2m_sde_gpu", "DPM++ 2M SDE"),
+    ("dpmpp_3m_sde", "DPM++ 3M SDE"),
+    ("dpmpp_3m_sde_gpu", "DPM++ 3M SDE"),
+    ("ddim", "DDIM"),
+    ("ddim_v3", "DDIM"),
+    ("ddpm", "DDPM"),
+    ("plms", "PLMS"),
+    ("deis", "DEIS"),
+    ("uni_pc", "UniPC"),
+    ("uni_pc_bh2", "UniPC"),
+    ("unipc", "UniPC"),
+    ("lcm", "LCM"),
+    ("restart", "Restart"),
+];
+
+/// Webui names of schedulers, appended to sampler name.
+/// Empty for default schedulers, that webui doesn't write
+const SCHEDULER_NAMES: &[(&str, &str)] = &[
+    ("normal", ""),
+    ("native", ""),
+    ("karras", "Karras"),
+    ("exponential", "Exponential"),
+    ("polyexponential", "Polyexponential"),
+    ("sgm_uniform", "SGM Uniform"),
+    ("simple", "Simple"),
+    ("ddim_uniform", "DDIM"),
+    ("beta", "Beta"),
+    ("kl_optimal", "KL Optimal"),
+    ("align_your_steps", "Align Your Steps"),
+];
+
+/// Convert sampler id and scheduler id (if generator writes it separately) to webui name,
+/// e.g. `dpmpp_2m` + `karras` to `DPM++ 2M Karras`, so they can be searched the same way.
+/// Unknown ids are kept as written
+pub fn webui_sampler(sampler: &str, scheduler: Option<&str>) -> String {
+    let lookup = |names: &[(&str, &'static str)], id: &str| names
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(id))
+        .map(|(_, name)| *name);
+
+    let mut name = lookup(SAMPLER_NAMES, sampler).unwrap_or(sampler).to_string();
+    
+    let scheduler = scheduler.map(|s| lookup(SCHEDULER_NAMES, s).unwrap_or(s));
+    if let Some(scheduler) = scheduler.filter(|s| !s.is_empty()) {
+        name.push(' ');
+        name.push_str(scheduler);
+    }
+
+    name
+}
+
 impl MetadataSource {
     pub fn group_name(&self) -> &'static str {
         match self {
             MetadataSource::Passthrough => "Passthrough stub. You should not see this.",
             MetadataSource::Signature => "Signature",
             MetadataSource::Webui => "Webui generation seed",
+            MetadataSource::Sidecar => "Sidecar caption",
             MetadataSource::NovelAI => "NovelAI generation seed",
+            MetadataSource::ComfyUI => "ComfyUI generation seed",
+            MetadataSource::InvokeAI => "InvokeAI generation seed",
+            MetadataSource::Fooocus => "Fooocus generation seed",
             MetadataSource::Pixiv => "Pixiv illust",
         }
     }
@@ -54,7 +163,11 @@ impl MetadataSource {
             MetadataSource::Passthrough => "Passthrough stub. You should not see this.",
             MetadataSource::Signature => "Signature",
             MetadataSource::Webui => "Webui SD Metadata",
+            MetadataSource::Sidecar => "Sidecar caption file",
             MetadataSource::NovelAI => "NovelAI SD Metadata",
+            MetadataSource::ComfyUI => "ComfyUI SD Metadata",
+            MetadataSource::InvokeAI => "InvokeAI SD Metadata",
+            MetadataSource::Fooocus => "Fooocus SD Metadata",
             MetadataSource::Pixiv => "Pixiv illust metadata",
         }
     }
@@ -64,7 +177,11 @@ impl MetadataSource {
             MetadataSource::Passthrough => "Passthrough stub. You should not see this.",
             MetadataSource::Signature => "Signature",
             MetadataSource::Webui => "Webui",
+            MetadataSource::Sidecar => "Sidecar",
             MetadataSource::NovelAI => "NovelAI",
+            MetadataSource::ComfyUI => "ComfyUI",
+            MetadataSource::InvokeAI => "InvokeAI",
+            MetadataSource::Fooocus => "Fooocus",
             MetadataSource::Pixiv => "Pixiv",
         }
     }
@@ -75,16 +192,49 @@ impl MetadataSource {
             MetadataSource::NovelAI => novelai::parse_metadata(raw_meta),
             MetadataSource::Webui => webui::parse_metadata(raw_meta),
             MetadataSource::Pixiv => pixiv::parse_metadata(raw_meta),
+            MetadataSource::ComfyUI => comfyui::parse_metadata(raw_meta),
+            MetadataSource::InvokeAI => invokeai::parse_metadata(raw_meta),
+            MetadataSource::Fooocus => fooocus::parse_metadata(raw_meta),
             _ => vec![]
         }
     }
 
+    /// Extract generation parameters from raw metadata.
+    /// Returns `None` for sources that are not stable diffusion generators
+    pub fn generation_params(&self, raw_meta: &str) -> Option<GenerationParams> {
+        match self {
+            MetadataSource::Webui => Some(webui::generation_params(raw_meta)),
+            MetadataSource::NovelAI => novelai::generation_params(raw_meta),
+            MetadataSource::ComfyUI => comfyui::generation_params(raw_meta),
+            MetadataSource::InvokeAI => invokeai::generation_params(raw_meta),
+            MetadataSource::Fooocus => fooocus::generation_params(raw_meta),
+            _ => None
+        }
+    }
+
     /// Prettify raw metadata
     pub fn pretty_raw_meta(&self, raw_meta: &str) -> String {
         match self {
             MetadataSource::NovelAI => novelai::pretty_raw_meta(raw_meta),
             MetadataSource::Pixiv => pixiv::pretty_raw_meta(raw_meta),
+            MetadataSource::ComfyUI => comfyui::pretty_raw_meta(raw_meta),
+            MetadataSource::InvokeAI => invokeai::pretty_raw_meta(raw_meta),
+            MetadataSource::Fooocus => fooocus::pretty_raw_meta(raw_meta),
+            // Sidecar may be either plain text or json
+            MetadataSource::Sidecar => serde_json::from_str::<serde_json::Value>(raw_meta)
+                .ok()
+                .and_then(|v| serde_json::to_string_pretty(&v).ok())
+                .unwrap_or_else(|| raw_meta.to_string()),
             _ => raw_meta.to_string()
         }
     }
 }
+
+#[test]
+fn test_webui_sampler() {
+    assert_eq!(webui_sampler("dpmpp_2m", Some("karras")), "DPM++ 2M Karras");
+    assert_eq!(webui_sampler("dpmpp_2m_sde_gpu", Some("exponential")), "DPM++ 2M SDE Exponential");
+    assert_eq!(webui_sampler("euler_ancestral", Some("normal")), "Euler a");
+    assert_eq!(webui_sampler("euler_a", None), "Euler a");
+    assert_eq!(webui_sampler("my_sampler", Some("my_scheduler")), "my_sampler my_scheduler");
+}
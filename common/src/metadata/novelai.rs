@@ -1,8 +1,9 @@
 use std::borrow::Cow;
 
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
-use super::ParsedMeta;
+use super::{ParsedMeta, GenerationParams, webui_sampler};
 
 /// Novel AI image metadata, Comment section
 #[derive(Serialize, Deserialize)]
@@ -17,6 +18,64 @@ pub struct Metadata<'a> {
     // This field is merged from Description PNG metadata
     #[serde(default)]
     pub prompt: Cow<'a, str>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cfg_rescale: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub noise_schedule: Option<Cow<'a, str>>,
+    /// V4 structured prompt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v4_prompt: Option<V4Prompt>,
+    /// V4 structured negative prompt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v4_negative_prompt: Option<V4Prompt>,
+}
+
+/// NovelAI V4 prompt: base caption and per-character captions
+#[derive(Serialize, Deserialize)]
+pub struct V4Prompt {
+    pub caption: V4Caption,
+    /// Whether character positions were specified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_coords: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct V4Caption {
+    pub base_caption: String,
+    #[serde(default)]
+    pub char_captions: Vec<CharCaption>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CharCaption {
+    pub char_caption: String,
+    /// Positions of character in image, from 0 to 1
+    #[serde(default)]
+    pub centers: Vec<Center>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Center {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Metadata<'_> {
+    /// Character captions paired with negative ones
+    pub fn characters(&self) -> impl Iterator<Item = (&CharCaption, Option<&CharCaption>)> {
+        let negative = self.v4_negative_prompt
+            .iter()
+            .flat_map(|p| &p.caption.char_captions);
+
+        self.v4_prompt
+            .iter()
+            .flat_map(|p| &p.caption.char_captions)
+            .zip(negative.map(Some).chain(std::iter::repeat(None)))
+    }
 }
 
 fn kv(key: &str, val: impl ToString, wide: bool) -> (String, String, bool) {
@@ -30,8 +89,8 @@ pub fn parse_metadata(raw_meta: &str) -> ParsedMeta {
         .expect("failed to parse metadata");
     
     let mut parsed = vec![
-        kv("Prompt", meta.prompt, true),
-        kv("Negative prompt", meta.uc, true),
+        kv("Prompt", &meta.prompt, true),
+        kv("Negative prompt", &meta.uc, true),
         kv("Steps", meta.steps, false),
         kv("CFG Scale", meta.scale, false),
         kv("Sampler", meta.sampler, false),
@@ -45,6 +104,37 @@ pub fn parse_metadata(raw_meta: &str) -> ParsedMeta {
     if let Some(v) = meta.noise {
         parsed.push(kv("Noise", v, false));
     }
+
+    if let Some(v) = meta.cfg_rescale {
+        parsed.push(kv("CFG Rescale", v, false));
+    }
+
+    if let Some(v) = &meta.noise_schedule {
+        parsed.push(kv("Noise Schedule", v, false));
+    }
+
+    let use_coords = meta.v4_prompt
+        .as_ref()
+        .and_then(|p| p.use_coords)
+        .unwrap_or_default();
+
+    // Each character has its own section
+    for (i, (pos, neg)) in meta.characters().enumerate() {
+        let name = format!("Character {}", i + 1);
+        parsed.push(kv(&name, &pos.char_caption, true));
+
+        if let Some(neg) = neg.filter(|n| !n.char_caption.is_empty()) {
+            parsed.push(kv(&format!("{name} negative"), &neg.char_caption, true));
+        }
+
+        if use_coords && !pos.centers.is_empty() {
+            let centers = pos.centers
+                .iter()
+                .map(|c| format!("({}, {})", c.x, c.y))
+                .join(", ");
+            parsed.push(kv(&format!("{name} position"), centers, false));
+        }
+    }
     
     parsed
 }
@@ -55,3 +145,38 @@ pub fn pretty_raw_meta(raw_meta: &str) -> String {
 
     serde_json::to_string_pretty(&meta).unwrap() 
 }
+
+/// Widen through shortest decimal representation,
+/// so `5.1` is stored as `5.1` and not as `5.099999904632568`
+fn widen(v: f32) -> f64 {
+    v.to_string().parse().unwrap_or(v.into())
+}
+
+pub fn generation_params(raw_meta: &str) -> Option<GenerationParams> {
+    let meta: Metadata = serde_json::from_str(raw_meta).ok()?;
+
+    Some(GenerationParams {
+        steps: Some(meta.steps),
+        sampler: Some(webui_sampler(
+            meta.sampler.strip_prefix("k_").unwrap_or(meta.sampler),
+            meta.noise_schedule.as_deref()
+        )),
+        cfg: Some(widen(meta.scale)),
+        seed: Some(meta.seed),
+        width: meta.width,
+        height: meta.height,
+        model: None,
+        model_hash: None,
+        denoise: meta.strength.map(widen),
+        loras: vec![],
+    })
+}
+
+#[test]
+fn test_generation_params() {
+    let raw_meta = r#"{"steps": 28, "sampler": "k_euler", "seed": 1, "strength": 0.7, 
+        "noise": 0.0, "scale": 5.1, "uc": ""}"#;
+    let params = generation_params(raw_meta).unwrap();
+    assert_eq!(params.cfg, Some(5.1));
+    assert_eq!(params.denoise, Some(0.7));
+}
@@ -0,0 +1,310 @@
+//! Stable diffusion webui prompt grammar
+//!
+//! Reference: https://github.com/AUTOMATIC1111/stable-diffusion-webui/wiki/Features
+//!
+//! - `(term)`, `[term]`, `(term:1.5)` - attention, weights of nested groups are multiplied
+//! - `[from:to:0.5]`, `[to:0.5]` - prompt editing, both sides are yielded
+//! - `[a|b]` - alternation, every alternative is yielded
+//! - `AND`, `BREAK` - separators
+//! - `<lora:name:0.8>`, `<lyco:name:0.8>`, `<hypernet:name:1>` - extra networks
+//! - `embedding:name` - embedding reference (ComfyUI syntax)
+//! - `\(` - escaped brace
+use std::{iter::Peekable, str::Chars};
+
+/// Weight multiplier of `()` (or divider of `[]`)
+const EMPHASIS: f32 = 1.1;
+
+/// Max nesting level of groups, deeper groups are kept as text
+const MAX_DEPTH: usize = 32;
+
+/// Known extensions of embedding files, stripped from names
+const EMBEDDING_EXTS: &[&str] = &[".safetensors", ".pt", ".bin"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TermKind {
+    /// Plain prompt term
+    Tag,
+    Lora,
+    Hypernetwork,
+    Embedding,
+}
+
+/// Concrete term of the prompt
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    /// Term text, unescaped, or extra network name
+    pub text: String,
+    /// Attention weight, or extra network multiplier
+    pub weight: f32,
+    pub kind: TermKind,
+}
+
+impl Term {
+    fn new(text: &str, weight: f32, kind: TermKind) -> Self {
+        Self { text: text.to_string(), weight, kind }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    /// Nesting level of current group
+    depth: usize,
+}
+
+/// Parse prompt to list of terms in order of appearance
+pub fn parse_prompt(prompt: &str) -> Vec<Term> {
+    Parser { chars: prompt.chars().peekable(), depth: 0 }.sequence(&[])
+}
+
+/// Split text into terms by `AND` and `BREAK` keywords, and push them
+fn flush(text: &mut String, terms: &mut Vec<Term>) {
+    let words: Vec<_> = text.split_whitespace().collect();
+
+    for part in words.split(|w| matches!(*w, "AND" | "BREAK")) {
+        let part = part.join(" ");
+        // Bare numbers are weights of `AND` parts or leftovers of broken syntax
+        if !part.is_empty() && part.parse::<f32>().is_err() {
+            terms.push(Term::new(&part, 1.0, TermKind::Tag));
+        }
+    }
+
+    text.clear();
+}
+
+/// Multiply weights of plain terms.
+/// Extra network multipliers are not affected by attention
+fn scale(mut terms: Vec<Term>, weight: f32) -> Vec<Term> {
+    for t in terms.iter_mut().filter(|t| t.kind == TermKind::Tag) {
+        t.weight *= weight;
+    }
+    terms
+}
+
+impl Parser<'_> {
+    /// Parse raw text of current group
+    fn nested(&self, raw: &str) -> Vec<Term> {
+        Parser { chars: raw.chars().peekable(), depth: self.depth }.sequence(&[])
+    }
+
+    /// Parse terms until one of `stops` chars (not consumed) or end of prompt
+    fn sequence(&mut self, stops: &[char]) -> Vec<Term> {
+        let mut terms = vec![];
+        let mut text = String::new();
+
+        while let Some(&c) = self.chars.peek() {
+            // `embedding:name` has `:` inside, so check it before stops
+            if c == ':' && text.rsplit(char::is_whitespace).next() == Some("embedding") {
+                self.chars.next();
+                text.truncate(text.len() - "embedding".len());
+                flush(&mut text, &mut terms);
+
+                let mut name = String::new();
+                while let Some(c) = self.chars.next_if(|c| !c.is_whitespace() && !",()[]|:<>".contains(*c)) {
+                    name.push(c);
+                }
+                let name = EMBEDDING_EXTS
+                    .iter()
+                    .find_map(|ext| name.strip_suffix(ext))
+                    .unwrap_or(&name);
+                if !name.is_empty() {
+                    terms.push(Term::new(name, 1.0, TermKind::Embedding));
+                }
+                continue;
+            }
+
+            if stops.contains(&c) {
+                break;
+            }
+
+            self.chars.next();
+            match c {
+                '\\' => text.extend(self.chars.next()),
+                ',' | '\n' => flush(&mut text, &mut terms),
+                '(' | '[' if self.depth == MAX_DEPTH => {
+                    let closer = if c == '(' { ')' } else { ']' };
+                    text.push(c);
+                    text.push_str(&self.group_text(closer));
+                    text.extend(self.chars.next_if_eq(&closer));
+                },
+                '(' | '[' => {
+                    flush(&mut text, &mut terms);
+                    self.depth += 1;
+                    let group = match c {
+                        '(' => self.attention(),
+                        _ => self.bracket(),
+                    };
+                    self.depth -= 1;
+                    terms.extend(group);
+                },
+                '<' => match self.extra_network() {
+                    Some(term) => {
+                        flush(&mut text, &mut terms);
+                        terms.push(term);
+                    },
+                    None => text.push(c),
+                },
+                // Unbalanced braces and stray separators
+                ')' | ']' | '|' | ':' => flush(&mut text, &mut terms),
+                c => text.push(c),
+            }
+        }
+
+        flush(&mut text, &mut terms);
+        terms
+    }
+
+    /// Take raw text until `closer` (not consumed) on the same nesting level
+    fn group_text(&mut self, closer: char) -> String {
+        let mut raw = String::new();
+        let mut depth = 0usize;
+
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                c if c == closer && depth == 0 => break,
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth = depth.saturating_sub(1),
+                '\\' => {
+                    raw.push(c);
+                    self.chars.next();
+                    raw.extend(self.chars.next());
+                    continue;
+                },
+                _ => (),
+            }
+            raw.push(c);
+            self.chars.next();
+        }
+
+        raw
+    }
+
+    /// `(term)` or `(term:weight)`, opening brace is consumed
+    fn attention(&mut self) -> Vec<Term> {
+        let mut terms = self.sequence(&[')', ':']);
+        let mut weight = EMPHASIS;
+
+        if self.chars.next_if_eq(&':').is_some() {
+            let raw = self.group_text(')');
+            match raw.trim().parse() {
+                Ok(w) => weight = w,
+                Err(_) => terms.extend(self.nested(&raw)),
+            }
+        }
+
+        // Closing brace (may be missing at the end of prompt)
+        self.chars.next();
+        scale(terms, weight)
+    }
+
+    /// `[term]`, `[from:to:when]`, `[to:when]` or `[a|b|c]`, opening bracket is consumed
+    fn bracket(&mut self) -> Vec<Term> {
+        let mut terms = self.sequence(&[']', ':', '|']);
+
+        match self.chars.next() {
+            // Alternation
+            Some('|') => loop {
+                terms.extend(self.sequence(&[']', '|']));
+                if self.chars.next() != Some('|') {
+                    break terms
+                }
+            },
+            // Prompt editing
+            Some(':') => {
+                let raw = self.group_text(']');
+                self.chars.next();
+
+                match raw.rsplit_once(':') {
+                    // [from:to:when]
+                    Some((to, when)) if when.trim().parse::<f32>().is_ok() => {
+                        terms.extend(self.nested(to))
+                    },
+                    // [to:when]
+                    _ if raw.trim().parse::<f32>().is_ok() => (),
+                    _ => terms.extend(self.nested(&raw)),
+                }
+                terms
+            },
+            // De-emphasis
+            _ => scale(terms, 1.0 / EMPHASIS),
+        }
+    }
+
+    /// `<kind:name:multiplier>`, opening angle bracket is consumed.
+    /// Nothing is consumed if it isn't a known extra network
+    fn extra_network(&mut self) -> Option<Term> {
+        let mut ahead = self.chars.clone();
+        let mut raw = String::new();
+        loop {
+            match ahead.next()? {
+                '>' => break,
+                c => raw.push(c),
+            }
+        }
+
+        let (kind, args) = raw.split_once(':')?;
+        let kind = match kind.trim() {
+            "lora" | "lyco" => TermKind::Lora,
+            "hypernet" => TermKind::Hypernetwork,
+            _ => return None,
+        };
+
+        let mut args = args.split(':');
+        let name = args.next()?.trim();
+        let weight = args
+            .next()
+            .and_then(|w| w.trim().parse().ok())
+            .unwrap_or(1.0);
+
+        if name.is_empty() {
+            return None
+        }
+
+        self.chars = ahead;
+        Some(Term::new(name, weight, kind))
+    }
+}
+
+#[test]
+fn test_parse_prompt() {
+    let prompt = "masterpiece, (red hair, blue eyes:1.5), ((smile)), [blurry], \
+        [cat:dog:0.5], [forest|city], [sunset:10], \\(artist\\) BREAK 1girl AND sky:0.8, \
+        <lora:add_detail:0.7> (night <hypernet:anime:1>:1.2), embedding:easynegative.pt";
+    let terms: Vec<_> = parse_prompt(prompt)
+        .into_iter()
+        .map(|t| (t.text, (t.weight * 1000.).round() / 1000., t.kind))
+        .collect();
+
+    let tag = |text: &str, weight: f32| (text.to_string(), weight, TermKind::Tag);
+    assert_eq!(
+        terms,
+        [
+            tag("masterpiece", 1.),
+            tag("red hair", 1.5),
+            tag("blue eyes", 1.5),
+            tag("smile", 1.21),
+            tag("blurry", 0.909),
+            tag("cat", 1.),
+            tag("dog", 1.),
+            tag("forest", 1.),
+            tag("city", 1.),
+            tag("sunset", 1.),
+            tag("(artist)", 1.),
+            tag("1girl", 1.),
+            tag("sky", 1.),
+            ("add_detail".to_string(), 0.7, TermKind::Lora),
+            tag("night", 1.2),
+            ("anime".to_string(), 1., TermKind::Hypernetwork),
+            ("easynegative".to_string(), 1., TermKind::Embedding),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_deep_prompt() {
+    let depth = 100_000;
+    let prompt = format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+    let terms = parse_prompt(&prompt);
+
+    assert_eq!(terms.len(), 1);
+    assert_eq!(terms[0].text.matches('(').count(), depth - MAX_DEPTH);
+}
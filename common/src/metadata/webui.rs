@@ -2,7 +2,9 @@ use std::{borrow::Cow, iter::once};
 
 use itertools::Itertools;
 
-use crate::ParsedMeta;
+use crate::{ParsedMeta, GenerationParams};
+
+use super::prompt::{parse_prompt, TermKind};
 
 /// Iterate through webui metadata parameters
 ///
@@ -63,6 +65,22 @@ pub fn iter_metadata(raw: &str) -> impl Iterator<Item = (&str, Cow<'_, str>)> {
         .chain(ai_meta)
 }
 
+/// Names of textual inversion embeddings from `TI hashes` parameter.
+/// Webui references embeddings in prompt by bare name, so it's the only way to tell them from tags
+pub fn embedding_names(raw_meta: &str) -> Vec<String> {
+    iter_metadata(raw_meta)
+        .find(|(k, _)| *k == "TI hashes")
+        // `name: hash, name: hash`
+        .map(|(_, v)| v
+            .split(',')
+            .filter_map(|e| e.split_once(':'))
+            .map(|(name, _)| name.trim().to_owned())
+            .filter(|name| !name.is_empty())
+            .collect()
+        )
+        .unwrap_or_default()
+}
+
 pub fn parse_metadata(raw_meta: &str) -> ParsedMeta {
     iter_metadata(raw_meta)
         .map(|(k, v)| {
@@ -71,3 +89,62 @@ pub fn parse_metadata(raw_meta: &str) -> ParsedMeta {
         })
         .collect()
 }
+
+pub fn generation_params(raw_meta: &str) -> GenerationParams {
+    let mut params = GenerationParams::default();
+    let mut schedule = None;
+    let mut prompt_loras = vec![];
+
+    for (k, v) in iter_metadata(raw_meta) {
+        match k {
+            "Prompt" => prompt_loras = parse_prompt(&v)
+                .into_iter()
+                .filter(|t| t.kind == TermKind::Lora)
+                .map(|t| t.text)
+                .collect(),
+            "Steps" => params.steps = v.parse().ok(),
+            "Sampler" => params.sampler = Some(v.into_owned()),
+            "Schedule type" => schedule = Some(v),
+            "CFG scale" => params.cfg = v.parse().ok(),
+            "Seed" => params.seed = v.parse().ok(),
+            "Size" => if let Some((w, h)) = v.split_once('x') {
+                params.width = w.parse().ok();
+                params.height = h.parse().ok();
+            },
+            "Model" => params.model = Some(v.into_owned()),
+            "Model hash" => params.model_hash = Some(v.into_owned()),
+            "Denoising strength" => params.denoise = v.parse().ok(),
+            // `name: hash, name: hash`
+            "Lora hashes" => params.loras = v
+                .split(',')
+                .filter_map(|l| l.split_once(':'))
+                .map(|(name, hash)| (name.trim().to_owned(), Some(hash.trim().to_owned())))
+                .collect(),
+            _ => (),
+        }
+    }
+
+    // Newer versions write scheduler separately: `DPM++ 2M` + `Karras`
+    if let (Some(sampler), Some(schedule)) = (&mut params.sampler, schedule) {
+        if schedule != "Automatic" {
+            sampler.push(' ');
+            sampler.push_str(&schedule);
+        }
+    }
+
+    // Older versions don't write LoRA hashes, so take names from the prompt
+    for name in prompt_loras {
+        if !params.loras.iter().any(|(n, _)| *n == name) {
+            params.loras.push((name, None));
+        }
+    }
+
+    params
+}
+
+#[test]
+fn test_embedding_names() {
+    let raw_meta = "1girl, easynegative\nNegative prompt: bad_hands, lowres\n\
+        Steps: 20, Sampler: Euler a, TI hashes: \"easynegative: c74b4e810b03, bad_hands: 1234abcd\", Seed: 1";
+    assert_eq!(embedding_names(raw_meta), ["easynegative", "bad_hands"]);
+}
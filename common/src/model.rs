@@ -4,7 +4,7 @@ use chrono::{DateTime, Utc};
 use enum_iterator::Sequence;
 use serde::{Serialize, Deserialize};
 
-use crate::metadata::MetadataSource;
+use crate::{metadata::MetadataSource, search::escape_glob};
 
 pub type UtcDateTime = DateTime<Utc>;
 
@@ -40,6 +40,16 @@ pub struct Element {
     pub thumb_url: Option<String>,
     /// Whether element is animation
     pub animated: bool,
+    /// MIME type of element file
+    pub mime: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// File size in bytes
+    pub size: Option<u64>,
+    /// Duration of video in seconds
+    pub duration: Option<f64>,
+    /// Video codec
+    pub codec: Option<String>,
 }   
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
@@ -50,8 +60,16 @@ pub struct ElementMetadata {
     pub add_time: UtcDateTime,
     /// Time when element was created/modified
     pub file_time: Option<UtcDateTime>,
+    /// MD5 hash of element file (hex)
+    pub md5: String,
+    /// SHA-256 hash of element file (hex)
+    pub sha256: Option<String>,
+    /// Path that file had relative to input folder
+    pub orig_path: Option<String>,
     /// Tags of the element
     pub tags: Vec<Tag>,
+    /// Tags from negative prompt of the generation
+    pub neg_tags: Vec<Tag>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
@@ -78,6 +96,104 @@ pub struct ExternalMetadata {
     pub raw_meta: Option<String>
 }
 
+/// File that failed to import
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+pub struct ImportFailure {
+    /// Failure id
+    pub id: u32,
+    /// Current path to file
+    pub path: String,
+    /// Path relative to input folder, file will be moved there on retry
+    pub orig_path: String,
+    /// Error chain
+    pub error: String,
+    /// Time of the last failure
+    pub fail_time: UtcDateTime,
+}
+
+/// Directory of input folder
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+pub struct Collection {
+    /// Path relative to input folder, `/`-separated
+    pub path: String,
+    /// Count of elements in this directory and all nested ones
+    pub element_count: u32,
+}
+
+impl Collection {
+    /// Name of the directory
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+
+    /// Search query that matches all elements of this collection
+    pub fn query(&self) -> String {
+        format!("path:\"{}/*\"", escape_glob(&self.path))
+    }
+}
+
+/// What started import run
+#[cfg_attr(feature = "backend", derive(sqlx::Type))]
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[repr(u8)]
+pub enum ImportTrigger {
+    /// Import requested from dashboard
+    Manual   = 0,
+    /// Periodic scan of input folder
+    Periodic = 1,
+    /// Files appeared in watched input folder
+    Watcher  = 2,
+    /// Files were uploaded through API
+    Upload   = 3,
+    /// Failed import was retried
+    Retry    = 4,
+}
+
+impl ImportTrigger {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImportTrigger::Manual => "Manual",
+            ImportTrigger::Periodic => "Periodic",
+            ImportTrigger::Watcher => "Watcher",
+            ImportTrigger::Upload => "Upload",
+            ImportTrigger::Retry => "Retry",
+        }
+    }
+}
+
+/// Counters of import run
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Default, Debug)]
+pub struct ImportStats {
+    /// Files that were found (archives are counted by their contents)
+    pub files_seen: u32,
+    /// New elements
+    pub imported: u32,
+    /// Files that were already in DB
+    pub duplicates: u32,
+    /// Files that failed to import
+    pub failed: u32,
+    /// Elements that got metadata from external sources
+    pub metadata_fetched: u32,
+    /// Similarity groups that got new elements
+    pub groups_formed: u32,
+    /// Thumbnails that were made
+    pub thumbnails_made: u32,
+}
+
+/// Finished import run
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+pub struct ImportRun {
+    /// Run id
+    pub id: u32,
+    /// What started this run
+    pub trigger: ImportTrigger,
+    pub start_time: UtcDateTime,
+    pub end_time: UtcDateTime,
+    pub stats: ImportStats,
+    /// Error that interrupted the run
+    pub error: Option<String>,
+}
+
 /// Struct that represent state of some procedure, 
 /// where there are many similar operations that can be counted
 #[derive(Serialize, Deserialize, PartialEq, Clone, Default)]
@@ -113,6 +229,8 @@ pub enum TagType {
     Metadata  = 4,
     #[default]
     Tag       = 5,
+    /// LoRA, hypernetwork or embedding used in prompt
+    Model     = 6,
 }
 
 /// Database summary
@@ -144,6 +262,7 @@ impl FromStr for TagType {
             "character" => Self::Character,
             "title" => Self::Title,
             "metadata" => Self::Metadata,
+            "model" => Self::Model,
             _ => Self::Tag,
         };
         Ok(ty)
@@ -160,6 +279,7 @@ impl TagType {
             TagType::Title => "title",
             TagType::Metadata => "metadata",
             TagType::Tag => "tag",
+            TagType::Model => "model",
         }
     }
     /// Get capitalized type name
@@ -171,6 +291,7 @@ impl TagType {
             TagType::Title => "Title",
             TagType::Metadata => "Metadata",
             TagType::Tag => "Tag",
+            TagType::Model => "Model",
         }
     }
 }
@@ -1,5 +1,5 @@
 use serde::{Serialize, Deserialize};
-use crate::model::*;
+use crate::{model::*, MetadataSource};
 
 #[derive(Serialize, Deserialize, Default, PartialEq)]
 pub struct SearchRequest {
@@ -71,10 +71,13 @@ pub struct StatusResponse {
     pub group_elements: TaskStatus,
     pub make_thumbnails: TaskStatus,
     pub wiki_fetch: TaskStatus,
+    pub import_preview: TaskStatus,
+    pub model_scan: TaskStatus,
+    pub reparse_metadata: TaskStatus,
 }
 
 /// Reqquest that will activate one of backend services
-#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub enum ControlRequest {
     /// Manually start import task in strict sequence
     StartImport,
@@ -87,7 +90,15 @@ pub enum ControlRequest {
     /// Retry failed external source metadata imports
     RetryImports,
     /// Fetch fresh tag data from danbooru 
-    FetchWikis
+    FetchWikis,
+    /// Hash files in models folder and add them to model registry
+    ScanModels,
+    /// Parse metadata of stored elements again with current parsers.
+    /// Elements may be limited by search query and by source of their metadata
+    ReparseMetadata {
+        query: Option<String>,
+        source: Option<MetadataSource>,
+    },
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Default)]
@@ -100,4 +111,95 @@ pub struct EditTagsRequest {
     pub element_id: u32,
     pub add: Vec<String>,
     pub remove: Vec<String>,
+}
+
+/// Outcome of uploaded file import
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum UploadStatus {
+    /// File was added as new element
+    Added { id: u32 },
+    /// Element with the same hash already exists
+    Duplicate { id: u32 },
+    /// File couldn't be imported
+    Failed { error: String },
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub struct UploadResult {
+    pub filename: String,
+    pub status: UploadStatus,
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub struct UploadResponse {
+    pub files: Vec<UploadResult>,
+}
+
+/// What file was found to be a duplicate of
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum Duplicate {
+    /// Element that is already in DB
+    Element(u32),
+    /// Another file in input folder
+    File(String),
+}
+
+/// What would happen to file on import
+#[derive(Serialize, Deserialize, PartialEq)]
+pub struct ImportPreview {
+    /// Path relative to input folder
+    pub path: String,
+    /// Source of metadata that will be parsed from file.
+    /// `None` if file failed to be analyzed
+    pub source: Option<MetadataSource>,
+    /// Tags that will be added to element
+    pub tags: Vec<String>,
+    /// Image library failed to load this file
+    pub broken: bool,
+    pub duplicate: Option<Duplicate>,
+    /// Error that will prevent import
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub struct ImportPreviewResponse {
+    pub files: Vec<ImportPreview>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Default)]
+pub struct ImportFailuresResponse {
+    pub failures: Vec<ImportFailure>,
+}
+
+/// What to do with file that failed to import
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum ImportFailureAction {
+    /// Move file back to input folder and import it
+    Retry,
+    /// Delete file
+    Discard,
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub struct ImportFailureRequest {
+    pub id: u32,
+    pub action: ImportFailureAction,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Default)]
+pub struct ImportHistoryResponse {
+    /// Most recent runs first
+    pub runs: Vec<ImportRun>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Default)]
+pub struct CollectionsRequest {
+    /// Path of collection, or `None` for top-level collections
+    pub parent: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Default)]
+pub struct CollectionsResponse {
+    /// Nested collections of requested one
+    pub collections: Vec<Collection>,
 }
\ No newline at end of file
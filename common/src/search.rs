@@ -1,4 +1,3 @@
-use std::ops::Range;
 use once_cell::sync::Lazy;
 use regex::Regex;
 
@@ -13,16 +12,132 @@ pub static TAG_REX: Lazy<Regex> = Lazy::new(||
 pub enum Term<'q> {
     /// Just a tag (is_positive, body)
     Tag(bool, &'q str),
+    /// Tag in negative prompt of generation (is_positive, body)
+    NegTag(bool, &'q str),
     /// Element group
     Group(u32),
     /// External element group
     ExtGroup(u32),
     /// Search in external metadata
     Meta(&'q str),
+    /// Element with md5 hash (hex)
+    Md5(&'q str),
+    /// Element with sha256 hash (hex)
+    Sha256(&'q str),
+    /// Element with original path (relative to input folder) matching glob pattern
+    Path(&'q str),
+    /// Element with width in range
+    Width(Range),
+    /// Element with height in range
+    Height(Range),
+    /// Element with aspect ratio (width / height) in range
+    Ratio(Range),
+    /// Element with file size (bytes) in range
+    Size(Range),
+    /// Video with duration (seconds) in range
+    Duration(Range),
+    /// Generated with sampling steps in range
+    Steps(Range),
+    /// Generated with CFG scale in range
+    Cfg(Range),
+    /// Generated with img2img denoising strength in range
+    Denoise(Range),
+    /// Generated with sampler (`LIKE` pattern)
+    Sampler(&'q str),
+    /// Generated with checkpoint, matched by name or hash (`LIKE` pattern)
+    Model(&'q str),
     /// Raw text that do not match existing patterns
     Raw(&'q str),
 }
 
+/// Numeric condition of range meta-tag
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Range {
+    /// `value` or `=value`
+    Eq(f64),
+    /// `<value`
+    Lt(f64),
+    /// `<=value` or `..value`
+    Le(f64),
+    /// `>value`
+    Gt(f64),
+    /// `>=value` or `value..`
+    Ge(f64),
+    /// `from..to`, inclusive
+    Between(f64, f64),
+}
+
+impl Range {
+    /// Parse range, using `value` to parse its bounds
+    pub fn parse(s: &str, value: impl Fn(&str) -> Option<f64>) -> Option<Self> {
+        let range = if let Some(v) = s.strip_prefix(">=") {
+            Self::Ge(value(v)?)
+        } else if let Some(v) = s.strip_prefix("<=") {
+            Self::Le(value(v)?)
+        } else if let Some(v) = s.strip_prefix('>') {
+            Self::Gt(value(v)?)
+        } else if let Some(v) = s.strip_prefix('<') {
+            Self::Lt(value(v)?)
+        } else if let Some(v) = s.strip_prefix('=') {
+            Self::Eq(value(v)?)
+        } else if let Some((from, to)) = s.split_once("..") {
+            match (from, to) {
+                ("", to) => Self::Le(value(to)?),
+                (from, "") => Self::Ge(value(from)?),
+                (from, to) => Self::Between(value(from)?, value(to)?),
+            }
+        } else {
+            Self::Eq(value(s)?)
+        };
+
+        Some(range)
+    }
+}
+
+/// Parse plain number
+fn parse_number(s: &str) -> Option<f64> {
+    s.parse().ok().filter(|v: &f64| v.is_finite())
+}
+
+/// Parse number with optional unit suffix, that is multiplied by unit factor.
+/// `units` should be ordered so that suffixes of other units go last
+fn parse_with_units(s: &str, units: &[(&str, f64)]) -> Option<f64> {
+    let s = s.to_lowercase();
+    for (unit, factor) in units {
+        if let Some(v) = s.strip_suffix(unit) {
+            // Scaled value may overflow
+            return parse_number(v)
+                .map(|v| v * factor)
+                .filter(|v| v.is_finite())
+        }
+    }
+    parse_number(&s)
+}
+
+/// Parse file size: `512`, `300kb`, `5mb`, `1.5gb` (1024-based)
+fn parse_size(s: &str) -> Option<f64> {
+    parse_with_units(s, &[
+        ("kib", 1024.), ("mib", 1024. * 1024.), ("gib", 1024. * 1024. * 1024.),
+        ("kb", 1024.), ("mb", 1024. * 1024.), ("gb", 1024. * 1024. * 1024.),
+        ("k", 1024.), ("m", 1024. * 1024.), ("g", 1024. * 1024. * 1024.),
+        ("b", 1.),
+    ])
+}
+
+/// Parse duration: `10`, `10s`, `1.5m`, `1h`
+fn parse_duration(s: &str) -> Option<f64> {
+    parse_with_units(s, &[("ms", 0.001), ("s", 1.), ("m", 60.), ("h", 3600.)])
+}
+
+/// Parse aspect ratio: `16:9` or `1.5`
+fn parse_ratio(s: &str) -> Option<f64> {
+    match s.split_once(':') {
+        Some((w, h)) => Some(parse_number(w)? / parse_number(h)?)
+            .filter(|r| r.is_finite()),
+        None => parse_number(s),
+    }
+}
+
 /// Creates an iterator that will output parsed query parts
 pub fn parse_query(query: &str) -> impl Iterator<Item = Term<'_>> {
     parse_query_with_span(query)
@@ -42,9 +157,33 @@ pub fn parse_term(term: &str) -> Option<Term<'_>> {
             ("group", id) => id.parse().ok().map(Term::Group),
             ("extgroup", id) => id.parse().ok().map(Term::ExtGroup),
             ("meta", text) => Some(Term::Meta(text)),            
+            ("md5", hash) if is_hex(hash, 32) => Some(Term::Md5(hash)),
+            ("sha256", hash) if is_hex(hash, 64) => Some(Term::Sha256(hash)),
+            ("path", glob) => Some(Term::Path(glob)),
+            ("neg", tag) if is_tag(tag) => Some(Term::NegTag(true, tag)),
+            ("!neg", tag) if is_tag(tag) => Some(Term::NegTag(false, tag)),
+            // Invalid ranges are kept as raw terms
+            ("width", r) => Range::parse(r, parse_number).map(Term::Width)
+                .or(Some(Term::Raw(term))),
+            ("height", r) => Range::parse(r, parse_number).map(Term::Height)
+                .or(Some(Term::Raw(term))),
+            ("ratio", r) => Range::parse(r, parse_ratio).map(Term::Ratio)
+                .or(Some(Term::Raw(term))),
+            ("size", r) => Range::parse(r, parse_size).map(Term::Size)
+                .or(Some(Term::Raw(term))),
+            ("duration", r) => Range::parse(r, parse_duration).map(Term::Duration)
+                .or(Some(Term::Raw(term))),
+            ("steps", r) => Range::parse(r, parse_number).map(Term::Steps)
+                .or(Some(Term::Raw(term))),
+            ("cfg", r) => Range::parse(r, parse_number).map(Term::Cfg)
+                .or(Some(Term::Raw(term))),
+            ("denoise", r) => Range::parse(r, parse_number).map(Term::Denoise)
+                .or(Some(Term::Raw(term))),
+            ("sampler", name) => Some(Term::Sampler(name)),
+            ("model", name) => Some(Term::Model(name)),
             _ => Some(Term::Raw(term)),
         }
-    } else if !TAG_REX.is_match(term) {
+    } else if is_tag(term) {
         // Allow only valid tags
         let pos = !term.starts_with('!');
         Some(Term::Tag(pos, if pos { term } else { &term[1..] }))
@@ -53,16 +192,45 @@ pub fn parse_term(term: &str) -> Option<Term<'_>> {
     }
 }
 
+/// Check if `s` is valid tag
+fn is_tag(s: &str) -> bool {
+    !s.is_empty() && !TAG_REX.is_match(s)
+}
+
+/// Check if `s` is hex string of length `len`
+fn is_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Escape glob wildcards and quotes, so `s` is matched literally by quoted `path:` term
+pub fn escape_glob(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '*' | '?' | '[' => {
+                out.push('[');
+                out.push(ch);
+                out.push(']');
+            },
+            '"' => out.push_str("\\\""),
+            ch => out.push(ch)
+        }
+    }
+    out
+}
+
 /// Creates an iterator that will output parsed query parts with source span.
 ///
 /// Returns `(byte_span, char_span, term)`
 pub fn parse_query_with_span(
     query: &str
-) -> impl Iterator<Item = (Range<usize>, Range<usize>, Term<'_>)> {
+) -> impl Iterator<Item = (std::ops::Range<usize>, std::ops::Range<usize>, Term<'_>)> {
     
     let mut span = 0..0;
     let mut char_span = 0..0;
     let mut in_quote = false;
+    // Previous char was unescaped backslash
+    let mut escaped = false;
     let mut iter = query.chars().peekable();
 
     std::iter::from_fn(move || {
@@ -78,6 +246,7 @@ pub fn parse_query_with_span(
                     char_span.end += 1;
                     span.start = span.end;
                     char_span.start = char_span.end;
+                    escaped = false;
 
                     // Special case for last char
                     if iter.peek().is_none() {
@@ -96,10 +265,11 @@ pub fn parse_query_with_span(
                 
                 // Text
                 ch => {
-                    // Quote
-                    if ch == '"' {
+                    // Quote, unless it's escaped as `\"`
+                    if ch == '"' && !escaped {
                         in_quote = !in_quote;
                     }
+                    escaped = ch == '\\' && !escaped;
                     
                     span.end += ch.len_utf8();
                     char_span.end += 1;
@@ -130,4 +300,51 @@ fn test_parse_with_span() {
         ].as_slice(), 
         terms.as_slice()
     );
+}
+
+#[test]
+fn test_parse_ranges() {
+    let query = "width:>=1024 height:512..768 ratio:16:9 size:>5mb duration:<10s width:..2k size:1e308gb";
+    let terms: Vec<_> = parse_query(query).collect();
+    assert_eq!(
+        [
+            Term::Width(Range::Ge(1024.)),
+            Term::Height(Range::Between(512., 768.)),
+            Term::Ratio(Range::Eq(16. / 9.)),
+            Term::Size(Range::Gt(5. * 1024. * 1024.)),
+            Term::Duration(Range::Lt(10.)),
+            Term::Raw("width:..2k"),
+            Term::Raw("size:1e308gb"),
+        ].as_slice(),
+        terms.as_slice()
+    );
+}
+
+#[test]
+fn test_parse_escaped_quote() {
+    let query = format!("path:\"{}/*\" end", escape_glob("a \"b\" [c]"));
+    let terms: Vec<_> = parse_query(&query).collect();
+    assert_eq!(
+        [
+            Term::Path("a \\\"b\\\" [[]c]/*"),
+            Term::Tag(true, "end"),
+        ].as_slice(),
+        terms.as_slice()
+    );
+}
+
+#[test]
+fn test_parse_neg_tags() {
+    let query = "1girl neg:bad_hands !neg:lowres neg: neg:a,b";
+    let terms: Vec<_> = parse_query(query).collect();
+    assert_eq!(
+        [
+            Term::Tag(true, "1girl"),
+            Term::NegTag(true, "bad_hands"),
+            Term::NegTag(false, "lowres"),
+            Term::Raw("neg:"),
+            Term::Raw("neg:a,b"),
+        ].as_slice(),
+        terms.as_slice()
+    );
 }
\ No newline at end of file
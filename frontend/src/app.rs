@@ -3,6 +3,7 @@ use yew::set_custom_panic_hook;
 use crate::component::prelude::*;
 use crate::component::input::InputAutocomplete;
 use crate::component::link::AppLink;
+use crate::page::collections::CollectionsPage;
 use crate::page::dashboard::Dashboard;
 use crate::page::element::ElementPage;
 use crate::page::index::Index;
@@ -14,6 +15,7 @@ pub fn switch(route: Route) -> Html {
         Route::Element { id } => html! { <ElementPage {id} /> },
         Route::Tag { id } => html! { <TagPage {id} /> },
         Route::Dashboard => html! { <Dashboard /> },
+        Route::Collections => html! { <CollectionsPage /> },
         _ => html! {
             <div class="label">{ "Not Found" }</div>
         }
@@ -53,6 +55,11 @@ fn Root() -> Html {
                     query={search.clone()}>
                     { "Dashboard" }
                 </AppLink<SearchQuery>>
+                <AppLink<()> 
+                    class="collections-button" 
+                    route={Route::Collections} >
+                    { "Collections" }
+                </AppLink<()>>
             </div>
             <div class="page-content">
                 <Switch<Route> render={switch} />
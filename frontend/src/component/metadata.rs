@@ -3,13 +3,31 @@ use super::prelude::*;
 /// Element metadata props
 #[derive(PartialEq, Properties)]
 pub struct MetadataProps {
+    pub element: Element,
     pub meta: ElementMetadata,
     pub on_show_raw_meta: Callback<String>
 }
 
+/// Format byte count with binary units
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024. && unit < UNITS.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{size:.1} {}", UNITS[unit]),
+    }
+}
+
 /// Element metadata (excluding tags)
 #[function_component]
 pub fn Metadata(props: &MetadataProps) -> Html {
+    let element = &props.element;
+
     let mut links = props.meta.ext_meta
         .iter()
         .filter_map(|m| m.src_link.as_ref().map(|href|
@@ -101,6 +119,50 @@ pub fn Metadata(props: &MetadataProps) -> Html {
                 { "Created at: " }{ props.meta.file_time }
             </div>
             { for times }
+            if let Some(orig_path) = &props.meta.orig_path {
+                <div class="section-label">
+                    { "Original path" }
+                </div>
+                <div class="section-part">
+                    { orig_path }
+                </div>
+            }
+            <div class="section-label">
+                { "File" }
+            </div>
+            if let Some(mime) = &element.mime {
+                <div class="section-part">
+                    { "Type: " }{ mime }
+                </div>
+            }
+            if let (Some(width), Some(height)) = (element.width, element.height) {
+                <div class="section-part">
+                    { "Resolution: " }{ width }{ "x" }{ height }
+                </div>
+            }
+            if let Some(size) = element.size {
+                <div class="section-part">
+                    { "Size: " }{ format_size(size) }
+                </div>
+            }
+            if let Some(duration) = element.duration {
+                <div class="section-part">
+                    { "Duration: " }{ format!("{duration:.1} s") }
+                </div>
+            }
+            if let Some(codec) = &element.codec {
+                <div class="section-part">
+                    { "Codec: " }{ codec }
+                </div>
+            }
+            <div class="section-part hash">
+                { "MD5: " }{ &props.meta.md5 }
+            </div>
+            if let Some(sha256) = &props.meta.sha256 {
+                <div class="section-part hash">
+                    { "SHA-256: " }{ sha256 }
+                </div>
+            }
             { for metadata_sections }
         </div>
     }
@@ -15,6 +15,10 @@ pub struct Props {
     /// `(added, removed) -> ()`
     #[prop_or_default]
     pub oncommit: Callback<(Vec<String>, Vec<String>)>,
+    /// Tags are from negative prompt: search by them with `neg:`
+    /// and hide counts, as they're counts of positive tags
+    #[prop_or_default]
+    pub negative: bool,
 }
 
 /// Element tag list, with links to tag edit page and search by tag
@@ -60,6 +64,7 @@ impl Component for TagList {
                 // Alphabet order
                 tags.sort_by_key(|t| &t.name);
         
+                let negative = ctx.props().negative;
                 let tags = tags.iter()
                     .map(|t| html! {
                         <>
@@ -73,7 +78,11 @@ impl Component for TagList {
                         <AppLink<SearchQuery> 
                             class="tag-info"
                             route={Route::Index}
-                            query={SearchQuery { query: t.name.clone() }}>
+                            query={SearchQuery { query: if negative {
+                                format!("neg:{}", t.name)
+                            } else {
+                                t.name.clone()
+                            }}}>
                             // Strikethrough if hidden
                             <@{if t.hidden { "s" } else { "div" }}>
                                 { t.pretty_name() }
@@ -81,9 +90,11 @@ impl Component for TagList {
                                     <i>{ " " }{ alt_name }</i>
                                 }
                             </@>
-                            <div class="tag-count">
-                                { t.count }
-                            </div>
+                            if !negative {
+                                <div class="tag-count">
+                                    { t.count }
+                                </div>
+                            }
                         </AppLink<SearchQuery>>
                         </>                
                     });
@@ -126,10 +137,10 @@ impl Component for TagList {
         let onsubmit = ctx.link().callback(Msg::Add);
         
         html! {
-            <div class="tag-list">
+            <div class={classes!("tag-list", ctx.props().negative.then_some("negative"))}>
                 <div class="header">
                     <div class="tags-label">
-                        { "Tags" }
+                        { if ctx.props().negative { "Negative tags" } else { "Tags" } }
                     </div>
                     if !ctx.props().read_only {
                         <div class="edit-controls">
@@ -0,0 +1,86 @@
+use crate::component::link::AppLink;
+
+use super::prelude::*;
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct CollectionsQuery {
+    /// Path of opened collection, top-level collections are shown if `None`
+    pub path: Option<String>,
+}
+
+/// Page that allows to browse input folder directories as collections
+#[function_component]
+pub fn CollectionsPage() -> Html {
+    let query: CollectionsQuery = use_location()
+        .expect("cannot access location")
+        .query()
+        .unwrap_or_default();
+
+    let resp = use_state(CollectionsResponse::default);
+
+    {
+        let resp = resp.clone();
+        use_effect_with_deps(|query| {
+            let req = CollectionsRequest {
+                parent: query.path.clone()
+            };
+
+            wasm_bindgen_futures::spawn_local(async move {
+                let data = backend_post!(&req, "/v1/collections")
+                    .await
+                    .expect("failed to fetch collections");
+                resp.set(data);
+            });
+        }, query.clone());
+    }
+
+    // Link to parent collection
+    let up = query.path.as_ref().map(|path| {
+        let parent = CollectionsQuery {
+            path: path.rsplit_once('/').map(|(p, _)| p.to_owned())
+        };
+        html! {
+            <AppLink<CollectionsQuery> 
+                class="collection-up" 
+                route={Route::Collections} 
+                query={parent}>
+                { "Up" }
+            </AppLink<CollectionsQuery>>
+        }
+    });
+
+    let collections = resp.collections
+        .iter()
+        .map(|c| {
+            let open = CollectionsQuery { path: Some(c.path.clone()) };
+            let search = SearchQuery { query: c.query() };
+            html! {
+                <div class="collection">
+                    <AppLink<CollectionsQuery> 
+                        class="collection-name" 
+                        route={Route::Collections} 
+                        query={open}>
+                        { c.name() }
+                    </AppLink<CollectionsQuery>>
+                    <AppLink<SearchQuery> 
+                        class="collection-elements" 
+                        route={Route::Index} 
+                        query={search}>
+                        { c.element_count }{ " elements" }
+                    </AppLink<SearchQuery>>
+                </div>
+            }
+        });
+
+    html! {
+        <div class="collections-page">
+            <div class="collection-header">
+                { for up }
+                <div class="collection-path">
+                    { query.path.as_deref().unwrap_or("/") }
+                </div>
+            </div>
+            { for collections }
+        </div>
+    }
+}
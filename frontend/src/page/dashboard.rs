@@ -10,6 +10,8 @@ use super::prelude::*;
 pub struct Dashboard {
     status: StatusResponse,
     summary: SummaryResponse,
+    failures: ImportFailuresResponse,
+    history: ImportHistoryResponse,
     log_ref: NodeRef,
     /// False if log wasn't scrolled to the end
     init_scroll: bool,
@@ -19,7 +21,22 @@ pub enum Msg {
     Tick,
     Update(StatusResponse, String),
     Summary(SummaryResponse),
-    Control(ControlRequest)
+    Control(ControlRequest),
+    FetchFailures,
+    Failures(ImportFailuresResponse),
+    FailureAction(ImportFailureRequest),
+    FetchHistory,
+    History(ImportHistoryResponse),
+}
+
+/// Statuses of tasks that make import run
+fn import_tasks(status: &StatusResponse) -> [&TaskStatus; 4] {
+    [
+        &status.scan_files, 
+        &status.update_metadata, 
+        &status.group_elements, 
+        &status.make_thumbnails
+    ]
 }
 
 #[derive(PartialEq, Properties)]
@@ -48,6 +65,8 @@ impl Component for Dashboard {
                 .expect("failed to fetch summary");
             Msg::Summary(resp)
         });
+        ctx.link().send_message(Msg::FetchFailures);
+        ctx.link().send_message(Msg::FetchHistory);
         Self::default()
     }
 
@@ -57,7 +76,10 @@ impl Component for Dashboard {
             ("Metadata update", &self.status.update_metadata),
             ("Group elements", &self.status.group_elements),
             ("Make thumbnails", &self.status.make_thumbnails),
-            ("Wiki fetch", &self.status.wiki_fetch)
+            ("Wiki fetch", &self.status.wiki_fetch),
+            ("Import preview", &self.status.import_preview),
+            ("Model scan", &self.status.model_scan),
+            ("Metadata re-parse", &self.status.reparse_metadata),
         ]
         .into_iter()
         .map(|(name, stat)| html! {
@@ -88,10 +110,12 @@ impl Component for Dashboard {
             (ControlRequest::FixThumbnails, "Fix thumbnails"),
             (ControlRequest::RetryImports, "Retry imports"),
             (ControlRequest::FetchWikis, "Fetch wikis"),
+            (ControlRequest::ScanModels, "Scan models"),
+            (ControlRequest::ReparseMetadata { query: None, source: None }, "Re-parse metadata"),
         ]
         .into_iter()
         .map(|(req, label)| {
-            let onclick = ctx.link().callback(move |_| Msg::Control(req));
+            let onclick = ctx.link().callback(move |_| Msg::Control(req.clone()));
             html! {
                 <div class="button section-data" {onclick}>
                     { label }
@@ -99,6 +123,71 @@ impl Component for Dashboard {
             }
         });
         
+        let failures = self.failures.failures
+            .iter()
+            .map(|f| {
+                let id = f.id;
+                let on_retry = ctx.link().callback(move |_| Msg::FailureAction(
+                    ImportFailureRequest { id, action: ImportFailureAction::Retry }
+                ));
+                let on_discard = ctx.link().callback(move |_| Msg::FailureAction(
+                    ImportFailureRequest { id, action: ImportFailureAction::Discard }
+                ));
+                html! {
+                    <div class="section-data import-failure">
+                        <div class="failure-path">{ &f.orig_path }</div>
+                        <div class="failure-time">{ f.fail_time }</div>
+                        <div class="failure-error">{ &f.error }</div>
+                        <div class="failure-controls">
+                            <div class="button" onclick={on_retry}>{ "Retry" }</div>
+                            <div class="button" onclick={on_discard}>{ "Discard" }</div>
+                        </div>
+                    </div>
+                }
+            });
+
+        let history = self.history.runs
+            .iter()
+            .map(|run| {
+                let ImportStats { 
+                    files_seen, 
+                    imported, 
+                    duplicates, 
+                    failed, 
+                    metadata_fetched, 
+                    groups_formed, 
+                    thumbnails_made 
+                } = run.stats;
+                let counters = [
+                    ("seen", files_seen),
+                    ("imported", imported),
+                    ("duplicates", duplicates),
+                    ("failed", failed),
+                    ("metadata", metadata_fetched),
+                    ("groups", groups_formed),
+                    ("thumbnails", thumbnails_made),
+                ]
+                .into_iter()
+                .map(|(name, count)| html! {
+                    <div class="run-counter">{ name }{ ": " }{ count }</div>
+                });
+
+                html! {
+                    <div class="section-data import-run">
+                        <div class="run-trigger">{ run.trigger.name() }</div>
+                        <div class="run-time">
+                            { run.start_time }{ " - " }{ run.end_time }
+                        </div>
+                        <div class="run-counters">
+                            { for counters }
+                        </div>
+                        if let Some(error) = &run.error {
+                            <div class="run-error">{ error }</div>
+                        }
+                    </div>
+                }
+            });
+
         html! {
             <div class="dashboard-page">
                 <div class="control-panel">
@@ -122,6 +211,18 @@ impl Component for Dashboard {
                         { "Control" }
                     </div>
                     { for controls }
+                    if !self.failures.failures.is_empty() {
+                        <div class="section-label">
+                            { "Import failures" }
+                        </div>
+                        { for failures }
+                    }
+                    if !self.history.runs.is_empty() {
+                        <div class="section-label">
+                            { "Import history" }
+                        </div>
+                        { for history }
+                    }
                 </div>
                 <div class="log-window">
                     <pre ref={self.log_ref.clone()}>
@@ -155,6 +256,11 @@ impl Component for Dashboard {
                 false
             },
             Msg::Update(stat, log_text) => {
+                // Import run may have finished, refresh its results
+                if import_tasks(&stat) != import_tasks(&self.status) {
+                    ctx.link().send_message(Msg::FetchHistory);
+                    ctx.link().send_message(Msg::FetchFailures);
+                }
                 self.status = stat;
                 let log: HtmlElement = self.log_ref.cast().unwrap();
                 log.set_inner_text(&log_text);
@@ -178,7 +284,42 @@ impl Component for Dashboard {
                     Msg::Tick
                 });
                 false
-            }
+            },
+            Msg::FetchFailures => {
+                ctx.link().send_future(async move {
+                    let resp = backend_get!("/v1/import_failures")
+                        .await
+                        .expect("failed to fetch import failures");
+                    Msg::Failures(resp)
+                });
+                false
+            },
+            Msg::Failures(failures) => {
+                self.failures = failures;
+                true
+            },
+            Msg::FailureAction(req) => {
+                ctx.link().send_future(async move {
+                    let _: () = backend_post!(&req, "/v1/import_failure")
+                        .await
+                        .expect("failed to send import failure request");
+                    Msg::FetchFailures
+                });
+                false
+            },
+            Msg::FetchHistory => {
+                ctx.link().send_future(async move {
+                    let resp = backend_get!("/v1/import_history")
+                        .await
+                        .expect("failed to fetch import history");
+                    Msg::History(resp)
+                });
+                false
+            },
+            Msg::History(history) => {
+                self.history = history;
+                true
+            },
         }
     }
 }
\ No newline at end of file
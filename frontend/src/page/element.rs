@@ -99,7 +99,15 @@ impl Component for ElementPage {
                                 content={metadata.tags.clone()} 
                                 read_only={false} 
                                 {oncommit} />
-                            <Metadata meta={metadata.clone()} {on_show_raw_meta}/>
+                            if !metadata.neg_tags.is_empty() {
+                                <TagList 
+                                    content={metadata.neg_tags.clone()} 
+                                    negative=true />
+                            }
+                            <Metadata 
+                                element={element.clone()} 
+                                meta={metadata.clone()} 
+                                {on_show_raw_meta}/>
                         </div>
                         <div id="element-container">
                             if let Some(raw_meta) = &self.raw_meta {
@@ -6,4 +6,5 @@ pub mod prelude {
 pub mod index;
 pub mod element;
 pub mod tag;
-pub mod dashboard;
\ No newline at end of file
+pub mod dashboard;
+pub mod collections;
\ No newline at end of file
@@ -27,6 +27,8 @@ pub enum Route {
     Index,
     #[at("/dashboard")]
     Dashboard,
+    #[at("/collections")]
+    Collections,
     #[at("/element/:id")]
     Element {
         id: u32